@@ -0,0 +1,197 @@
+use crate::condition::{ConditionResult, RequestContext};
+use crate::glob::Glob;
+use crate::policy::Statement;
+use crate::variables::{has_variables, resolve};
+
+// arn:partition:service:region:account-id:resource
+const ARN_SEGMENTS: usize = 6;
+
+// A statement applies to the resource either through `Resource`, or through
+// `NotResource`, which covers every resource except the listed ones.
+// Statements without either element are not scoped to a resource. Patterns
+// with policy variables are resolved against `context` if the statement
+// supports them, and when none of the others match, their unresolved
+// variables are reported as missing keys.
+pub fn check_resource_in_statement(
+    statement: &Statement,
+    resource_arn: &str,
+    context: &RequestContext,
+) -> ConditionResult {
+    let texts = statement.resource.iter().chain(statement.not_resource.iter()).flatten();
+    let mut unresolved = vec![];
+    let mut matches = false;
+    for (text, pattern) in texts.zip(statement.resource_patterns()) {
+        let matched = if statement.supports_variables() && has_variables(text) {
+            match resolve(text, context) {
+                Ok(resolved) => ArnPattern::resolved(&resolved).is_match(resource_arn),
+                Err(mut keys) => {
+                    unresolved.append(&mut keys);
+                    false
+                }
+            }
+        } else {
+            pattern.is_match(resource_arn)
+        };
+        if matched {
+            matches = true;
+            break;
+        }
+    }
+
+    if statement.resource.is_none() && statement.not_resource.is_none() {
+        return ConditionResult::Match;
+    }
+    if !matches && !unresolved.is_empty() {
+        return ConditionResult::MissingKeys(unresolved);
+    }
+    // `NotResource` applies when nothing matched.
+    if matches == statement.resource.is_some() {
+        ConditionResult::Match
+    } else {
+        ConditionResult::NoMatch
+    }
+}
+
+/// An IAM resource pattern, compiled once.
+/// Wildcards in the partition, service, region and account segments never
+/// span a `:`, while the resource segment is matched as a whole.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArnPattern {
+    Any,
+    Segments(Vec<Glob>),
+    Whole(Glob),
+}
+
+impl ArnPattern {
+    pub fn new(pattern: &str) -> Self {
+        if pattern == "*" {
+            return ArnPattern::Any;
+        }
+        let segments: Vec<&str> = pattern.splitn(ARN_SEGMENTS, ':').collect();
+        if segments.len() == ARN_SEGMENTS {
+            ArnPattern::Segments(segments.into_iter().map(Glob::new).collect())
+        } else {
+            ArnPattern::Whole(Glob::new(pattern))
+        }
+    }
+
+    /// A pattern with its policy variables resolved. See `Glob::resolved`.
+    pub fn resolved(pattern: &[(char, bool)]) -> Self {
+        if pattern == [('*', false)] {
+            return ArnPattern::Any;
+        }
+        let segments: Vec<&[(char, bool)]> = pattern.splitn(ARN_SEGMENTS, |(c, _)| *c == ':').collect();
+        if segments.len() == ARN_SEGMENTS {
+            ArnPattern::Segments(segments.into_iter().map(Glob::resolved).collect())
+        } else {
+            ArnPattern::Whole(Glob::resolved(pattern))
+        }
+    }
+
+    pub fn is_match(&self, arn: &str) -> bool {
+        match self {
+            ArnPattern::Any => true,
+            ArnPattern::Whole(glob) => glob.is_match(arn),
+            ArnPattern::Segments(globs) => {
+                let segments: Vec<&str> = arn.splitn(ARN_SEGMENTS, ':').collect();
+                segments.len() == ARN_SEGMENTS
+                    && globs.iter().zip(segments).all(|(glob, s)| glob.is_match(s))
+            }
+        }
+    }
+}
+
+pub fn arn_matches(pattern: &str, arn: &str) -> bool {
+    ArnPattern::new(pattern).is_match(arn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::policy;
+
+    fn statement(json: &str) -> Statement {
+        policy(&format!("[{}]", json)).statement.remove(0)
+    }
+
+    fn check(statement: &Statement, resource_arn: &str) -> ConditionResult {
+        check_resource_in_statement(statement, resource_arn, &RequestContext::new())
+    }
+
+    #[test]
+    fn wildcards_stay_within_their_segment() {
+        assert!(arn_matches("arn:aws:s3:::my-bucket/*", "arn:aws:s3:::my-bucket/a/b"));
+        assert!(arn_matches(
+            "arn:aws:iam::*:role/admin",
+            "arn:aws:iam::123456789012:role/admin"
+        ));
+        assert!(arn_matches(
+            "arn:aws:ec2:*:*:instance/*",
+            "arn:aws:ec2:eu-west-1:123456789012:instance/i-1"
+        ));
+        assert!(arn_matches(
+            "arn:aws:ec2:eu-west-?:*:instance/*",
+            "arn:aws:ec2:eu-west-1:123456789012:instance/i-1"
+        ));
+
+        // `*` in the region cannot swallow the account.
+        assert!(!arn_matches(
+            "arn:aws:ec2:*::instance/*",
+            "arn:aws:ec2:eu-west-1:123456789012:instance/i-1"
+        ));
+        assert!(!arn_matches(
+            "arn:aws:iam::*:role/admin",
+            "arn:aws:iam::123456789012:user/admin"
+        ));
+        assert!(!arn_matches(
+            "arn:aws:s3:::my-bucket/*",
+            "arn:aws:s3:::other-bucket/key"
+        ));
+    }
+
+    #[test]
+    fn resource_segment_keeps_its_colons() {
+        let pattern = "arn:aws:logs:us-east-1:123456789012:log-group:app:*";
+        assert!(matches!(ArnPattern::new(pattern), ArnPattern::Segments(_)));
+        assert!(arn_matches(
+            pattern,
+            "arn:aws:logs:us-east-1:123456789012:log-group:app:log-stream:web"
+        ));
+        assert!(!arn_matches(
+            pattern,
+            "arn:aws:logs:us-east-1:123456789012:log-group:other:log-stream:web"
+        ));
+    }
+
+    #[test]
+    fn patterns_with_fewer_segments_match_as_a_whole() {
+        let pattern = ArnPattern::new("arn:aws:*");
+        assert!(matches!(pattern, ArnPattern::Whole(_)));
+        assert!(pattern.is_match("arn:aws:s3:::my-bucket"));
+        assert!(pattern.is_match("arn:aws:iam::123456789012:role/admin"));
+        assert!(!pattern.is_match("arn:aws-cn:s3:::my-bucket"));
+
+        assert_eq!(ArnPattern::new("*"), ArnPattern::Any);
+        assert!(!arn_matches("arn:aws:s3:::my-bucket", "arn:aws:s3"));
+    }
+
+    #[test]
+    fn not_resource_applies_to_everything_else() {
+        let not_resource = statement(r#"{"Effect":"Deny","Action":"s3:*","NotResource":"arn:aws:s3:::public/*"}"#);
+        assert_eq!(check(&not_resource, "arn:aws:s3:::private/key"), ConditionResult::Match);
+        assert_eq!(
+            check(&not_resource, "arn:aws:s3:::public/key"),
+            ConditionResult::NoMatch
+        );
+
+        let resource = statement(r#"{"Effect":"Allow","Action":"s3:*","Resource":"arn:aws:s3:::public/*"}"#);
+        assert_eq!(check(&resource, "arn:aws:s3:::private/key"), ConditionResult::NoMatch);
+        assert_eq!(check(&resource, "arn:aws:s3:::public/key"), ConditionResult::Match);
+    }
+
+    #[test]
+    fn statements_without_resource_apply_to_any_resource() {
+        let unscoped = statement(r#"{"Effect":"Allow","Principal":"*","Action":"s3:GetObject"}"#);
+        assert_eq!(check(&unscoped, "arn:aws:s3:::my-bucket/key"), ConditionResult::Match);
+    }
+}
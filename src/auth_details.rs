@@ -0,0 +1,138 @@
+use crate::policy::PolicyDocument;
+use serde::Deserialize;
+use serde_json::Value;
+use urlencoding::decode;
+
+/// Output of `aws iam get-account-authorization-details`, used to run the
+/// analysis from a snapshot instead of live IAM calls.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct AuthorizationDetails {
+    pub user_detail_list: Vec<UserDetail>,
+    pub group_detail_list: Vec<GroupDetail>,
+    pub role_detail_list: Vec<RoleDetail>,
+    pub policies: Vec<ManagedPolicyDetail>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct UserDetail {
+    pub user_name: String,
+    pub user_id: String,
+    pub arn: String,
+    pub group_list: Vec<String>,
+    pub attached_managed_policies: Vec<AttachedPolicyDetail>,
+    pub user_policy_list: Vec<InlinePolicyDetail>,
+    pub permissions_boundary: Option<PermissionsBoundaryDetail>,
+    pub tags: Vec<TagDetail>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct GroupDetail {
+    pub group_name: String,
+    pub group_id: String,
+    pub arn: String,
+    pub attached_managed_policies: Vec<AttachedPolicyDetail>,
+    pub group_policy_list: Vec<InlinePolicyDetail>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct RoleDetail {
+    pub role_name: String,
+    pub role_id: String,
+    pub arn: String,
+    pub assume_role_policy_document: Option<Value>,
+    pub attached_managed_policies: Vec<AttachedPolicyDetail>,
+    pub role_policy_list: Vec<InlinePolicyDetail>,
+    pub permissions_boundary: Option<PermissionsBoundaryDetail>,
+    pub tags: Vec<TagDetail>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct AttachedPolicyDetail {
+    pub policy_name: String,
+    pub policy_arn: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct InlinePolicyDetail {
+    pub policy_name: String,
+    pub policy_document: Value,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct PermissionsBoundaryDetail {
+    pub permissions_boundary_type: String,
+    pub permissions_boundary_arn: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct TagDetail {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct ManagedPolicyDetail {
+    pub policy_name: String,
+    pub arn: String,
+    pub default_version_id: String,
+    pub policy_version_list: Vec<PolicyVersionDetail>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct PolicyVersionDetail {
+    pub document: Value,
+    pub version_id: String,
+    pub is_default_version: bool,
+}
+
+impl AuthorizationDetails {
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| e.to_string())
+    }
+
+    /// Default version document of a managed policy in the snapshot, or why
+    /// it could not be parsed.
+    pub fn managed_policy_document(&self, policy_arn: &str) -> Option<Result<PolicyDocument, String>> {
+        let policy = self.policies.iter().find(|p| p.arn == policy_arn)?;
+        let version = policy
+            .policy_version_list
+            .iter()
+            .find(|v| v.is_default_version || v.version_id == policy.default_version_id)?;
+        Some(parse_document(&version.document))
+    }
+
+    pub fn trust_policy(role: &RoleDetail) -> Option<Result<PolicyDocument, String>> {
+        Some(parse_document(role.assume_role_policy_document.as_ref()?))
+    }
+
+    /// Every inline policy, including the ones whose document could not be parsed.
+    pub fn inline_policies(inline: &[InlinePolicyDetail]) -> Vec<(String, Result<PolicyDocument, String>)> {
+        inline
+            .iter()
+            .map(|p| (p.policy_name.clone(), parse_document(&p.policy_document)))
+            .collect()
+    }
+}
+
+// The CLI prints documents as JSON objects, while the API returns them
+// as URL-encoded strings.
+fn parse_document(document: &Value) -> Result<PolicyDocument, String> {
+    match document {
+        Value::String(s) => {
+            let decoded = decode(s).map_err(|e| e.to_string())?;
+            decoded.parse().map_err(|e: serde_json::Error| e.to_string())
+        }
+        Value::Object(_) => serde_json::from_value(document.clone()).map_err(|e| e.to_string()),
+        _ => Err("the document is neither a JSON object nor a string".to_string()),
+    }
+}
@@ -0,0 +1,230 @@
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+const SERVICE_AUTHORIZATION: &str = include_str!("../data/service-authorization.json");
+
+/// A snapshot of the Service Authorization Reference for the most used AWS
+/// services, bundled with the crate so actions can be expanded and validated
+/// offline. Services missing from it are neither expanded nor validated.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ActionCatalog {
+    pub services: Vec<Service>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Service {
+    pub prefix: String,
+    pub name: String,
+    pub resource_types: Vec<ResourceType>,
+    /// Service-specific condition keys. Global `aws:` keys are not listed.
+    pub condition_keys: Vec<String>,
+    pub actions: Vec<Action>,
+    /// Whether `actions` lists every action of the service. Wildcards over a
+    /// service that is not complete can allow actions the catalog lacks.
+    #[serde(default)]
+    pub complete: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResourceType {
+    pub name: String,
+    /// ARN format, with `${Partition}`-style placeholders.
+    pub arn: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Action {
+    pub name: String,
+    pub access_level: AccessLevel,
+    /// Names of the service's resource types the action applies to.
+    /// Empty when it only supports `"Resource": "*"`.
+    pub resource_types: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum AccessLevel {
+    List,
+    Read,
+    Write,
+    #[serde(rename = "Permissions management")]
+    PermissionsManagement,
+    Tagging,
+}
+
+impl Display for AccessLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            AccessLevel::List => write!(f, "List"),
+            AccessLevel::Read => write!(f, "Read"),
+            AccessLevel::Write => write!(f, "Write"),
+            AccessLevel::PermissionsManagement => write!(f, "Permissions management"),
+            AccessLevel::Tagging => write!(f, "Tagging"),
+        }
+    }
+}
+
+/// What the catalog knows about an action name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionValidation {
+    Known,
+    UnknownAction,
+    /// The service is not in the snapshot, so the action could not be checked.
+    UnknownService,
+    /// Not of the form `service:Action`.
+    Malformed,
+}
+
+impl ActionCatalog {
+    pub fn bundled() -> &'static ActionCatalog {
+        static CATALOG: OnceLock<ActionCatalog> = OnceLock::new();
+        CATALOG.get_or_init(|| {
+            serde_json::from_str(SERVICE_AUTHORIZATION)
+                .expect("bundled service authorization snapshot is valid JSON")
+        })
+    }
+
+    pub fn service(&self, prefix: &str) -> Option<&Service> {
+        self.services
+            .iter()
+            .find(|s| s.prefix.eq_ignore_ascii_case(prefix))
+    }
+
+    /// Looks up `service:Action`, ignoring case like IAM does.
+    pub fn action(&self, action_name: &str) -> Option<(&Service, &Action)> {
+        let (prefix, name) = action_name.split_once(':')?;
+        let service = self.service(prefix)?;
+        let action = service
+            .actions
+            .iter()
+            .find(|a| a.name.eq_ignore_ascii_case(name))?;
+        Some((service, action))
+    }
+
+    /// Every action as `prefix:Action`.
+    pub fn actions(&self) -> impl Iterator<Item = String> + '_ {
+        self.services.iter().flat_map(|service| {
+            service
+                .actions
+                .iter()
+                .map(move |action| format!("{}:{}", service.prefix, action.name))
+        })
+    }
+
+    pub fn validate(&self, action_name: &str) -> ActionValidation {
+        let Some((prefix, name)) = action_name.split_once(':') else {
+            return ActionValidation::Malformed;
+        };
+        if prefix.is_empty() || name.is_empty() {
+            ActionValidation::Malformed
+        } else if self.service(prefix).is_none() {
+            ActionValidation::UnknownService
+        } else if self.action(action_name).is_none() {
+            ActionValidation::UnknownAction
+        } else {
+            ActionValidation::Known
+        }
+    }
+
+    /// Catalog actions that `input` may be a misspelling of, closest first.
+    /// Within a known service only its actions are considered.
+    ///
+    /// Candidates are ranked by edit distance rather than with the fuzzy
+    /// matcher of `complete`, which only finds candidates containing the input
+    /// as a subsequence. Typos break that: `s3:GetObjects` has an extra
+    /// character and `s3:GetObjcet` swaps two, so neither matches `s3:GetObject`.
+    pub fn suggest(&self, input: &str, limit: usize) -> Vec<String> {
+        let candidates = self.candidates(input);
+        let input_lower = input.to_lowercase();
+        let max_distance = (input.len() / 5).max(2);
+
+        let mut close: Vec<(usize, String)> = candidates
+            .into_iter()
+            .map(|candidate| (edit_distance(&input_lower, &candidate.to_lowercase()), candidate))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .collect();
+        close.sort();
+        close.into_iter().take(limit).map(|(_, name)| name).collect()
+    }
+
+    /// Catalog actions that fuzzy match `input`, best first, for completing
+    /// a partially typed action name.
+    pub fn complete(&self, input: &str, limit: usize) -> Vec<String> {
+        let matcher = SkimMatcherV2::default().ignore_case();
+        let mut scored: Vec<(i64, String)> = self
+            .candidates(input)
+            .into_iter()
+            .filter_map(|candidate| Some((matcher.fuzzy_match(&candidate, input)?, candidate)))
+            .collect();
+        scored.sort_by(|(a, a_name), (b, b_name)| b.cmp(a).then(a_name.cmp(b_name)));
+        scored.into_iter().take(limit).map(|(_, name)| name).collect()
+    }
+
+    fn candidates(&self, input: &str) -> Vec<String> {
+        match input.split_once(':').and_then(|(prefix, _)| self.service(prefix)) {
+            Some(service) => service
+                .actions
+                .iter()
+                .map(|a| format!("{}:{}", service.prefix, a.name))
+                .collect(),
+            None => self.actions().collect(),
+        }
+    }
+}
+
+// Levenshtein distance that also counts swapping two adjacent characters as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![(0..=b.len()).collect::<Vec<usize>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_counts_adjacent_swaps_as_one_edit() {
+        assert_eq!(edit_distance("getobject", "getobject"), 0);
+        assert_eq!(edit_distance("getobjct", "getobject"), 1);
+        assert_eq!(edit_distance("getobjects", "getobject"), 1);
+        assert_eq!(edit_distance("getobjcet", "getobject"), 1);
+        assert_eq!(edit_distance("putobject", "getobject"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn suggest_finds_close_misspellings_in_the_service() {
+        let catalog = ActionCatalog::bundled();
+        assert_eq!(catalog.suggest("s3:GetObjct", 3).first().map(String::as_str), Some("s3:GetObject"));
+        assert_eq!(catalog.suggest("S3:GETOBJCET", 3).first().map(String::as_str), Some("s3:GetObject"));
+        assert!(catalog.suggest("s3:GetObjct", 3).iter().all(|a| a.starts_with("s3:")));
+        assert!(catalog.suggest("s3:TotallyDifferent", 3).is_empty());
+    }
+
+    #[test]
+    fn complete_fuzzy_matches_partial_names() {
+        let catalog = ActionCatalog::bundled();
+        let completions = catalog.complete("s3:getobj", 5);
+        assert!(completions.contains(&"s3:GetObject".to_string()), "{:?}", completions);
+        assert!(completions.len() <= 5);
+        assert!(completions.iter().all(|a| a.starts_with("s3:")));
+        assert!(catalog.complete("s3:zzzz", 5).is_empty());
+    }
+}
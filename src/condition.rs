@@ -0,0 +1,658 @@
+use crate::arn::{arn_matches, ArnPattern};
+use crate::glob::{glob_matches, Glob};
+use crate::policy::Condition;
+use crate::variables::{has_variables, resolve, resolved_text, ResolvedPattern};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// Condition keys and values describing the request being checked.
+/// Keys are case-insensitive, like IAM condition keys.
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    values: HashMap<String, Vec<String>>,
+}
+
+impl RequestContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, key: &str, value: String) {
+        self.values
+            .entry(key.to_lowercase())
+            .or_default()
+            .push(value);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&[String]> {
+        self.values.get(&key.to_lowercase()).map(|v| v.as_slice())
+    }
+
+    /// Parse `key=value` pairs. Repeating a key makes it multivalued.
+    pub fn from_pairs(pairs: &[String]) -> Result<Self, String> {
+        let mut context = Self::new();
+        for pair in pairs {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("'{}' is not a valid context entry. Use 'key=value'.", pair))?;
+            context.insert(key.trim(), value.trim().to_string());
+        }
+        Ok(context)
+    }
+
+    /// Parse a JSON object mapping condition keys to a value or an array of values.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let parsed: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let object = parsed
+            .as_object()
+            .ok_or_else(|| "context file must be a JSON object".to_string())?;
+
+        let mut context = Self::new();
+        for (key, value) in object {
+            match value {
+                Value::Array(arr) => {
+                    for v in arr {
+                        context.insert(key, value_to_string(v));
+                    }
+                }
+                v => context.insert(key, value_to_string(v)),
+            }
+        }
+        Ok(context)
+    }
+
+    /// Every key, lowercased, with its values.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.values.iter().map(|(key, values)| (key.as_str(), values.as_slice()))
+    }
+
+    /// Adds the keys of `defaults` this context does not have.
+    pub fn merge_defaults(&mut self, defaults: &RequestContext) {
+        for (key, values) in &defaults.values {
+            if !self.values.contains_key(key) {
+                self.values.insert(key.clone(), values.clone());
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: RequestContext) {
+        for (key, mut values) in other.values {
+            self.values.entry(key).or_default().append(&mut values);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConditionResult {
+    Match,
+    NoMatch,
+    /// The outcome depends on context keys that were not supplied.
+    MissingKeys(Vec<String>),
+}
+
+// Every operator and key in a condition block must hold for it to match.
+// Policy variables in the values are only resolved when `variables` is set.
+pub fn evaluate_condition(condition: &Condition, context: &RequestContext, variables: bool) -> ConditionResult {
+    let mut missing_keys = vec![];
+    for (operator, keys) in condition {
+        for (key, expected) in keys {
+            match evaluate_operator(operator, key, expected, context, variables) {
+                ConditionResult::Match => {}
+                ConditionResult::NoMatch => return ConditionResult::NoMatch,
+                ConditionResult::MissingKeys(mut keys) => missing_keys.append(&mut keys),
+            }
+        }
+    }
+
+    if missing_keys.is_empty() {
+        ConditionResult::Match
+    } else {
+        ConditionResult::MissingKeys(missing_keys)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SetQualifier {
+    None,
+    ForAllValues,
+    ForAnyValue,
+}
+
+fn evaluate_operator(
+    operator: &str,
+    key: &str,
+    expected: &[String],
+    context: &RequestContext,
+    variables: bool,
+) -> ConditionResult {
+    let (qualifier, operator) = if let Some(op) = operator.strip_prefix("ForAllValues:") {
+        (SetQualifier::ForAllValues, op)
+    } else if let Some(op) = operator.strip_prefix("ForAnyValue:") {
+        (SetQualifier::ForAnyValue, op)
+    } else {
+        (SetQualifier::None, operator)
+    };
+    let (operator, if_exists) = match operator.strip_suffix("IfExists") {
+        Some(op) => (op, true),
+        None => (operator, false),
+    };
+
+    let actual = context.get(key);
+
+    if operator == "Null" {
+        // "true" means the key must be absent, "false" means it must be present.
+        let wants_absent = expected.iter().any(|v| v.eq_ignore_ascii_case("true"));
+        return to_result(actual.is_none() == wants_absent);
+    }
+
+    let Some(actual) = actual else {
+        if if_exists {
+            return ConditionResult::Match;
+        }
+        return ConditionResult::MissingKeys(vec![key.to_string()]);
+    };
+
+    let Some((base, negated)) = parse_operator(operator) else {
+        return ConditionResult::NoMatch;
+    };
+    let expected = match resolve_values(expected, context, variables) {
+        Ok(expected) => expected,
+        Err(keys) => return ConditionResult::MissingKeys(keys),
+    };
+
+    let value_matches = |value: &String| {
+        let any = expected.iter().any(|e| base_matches(base, e, value));
+        any != negated
+    };
+
+    let matched = match qualifier {
+        SetQualifier::ForAllValues => actual.iter().all(value_matches),
+        SetQualifier::ForAnyValue => actual.iter().any(value_matches),
+        SetQualifier::None if negated => actual.iter().all(value_matches),
+        SetQualifier::None => actual.iter().any(value_matches),
+    };
+    to_result(matched)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BaseOperator {
+    StringEquals,
+    StringEqualsIgnoreCase,
+    StringLike,
+    NumericEquals,
+    NumericLessThan,
+    NumericLessThanEquals,
+    NumericGreaterThan,
+    NumericGreaterThanEquals,
+    DateEquals,
+    DateLessThan,
+    DateLessThanEquals,
+    DateGreaterThan,
+    DateGreaterThanEquals,
+    Bool,
+    BinaryEquals,
+    IpAddress,
+    ArnEquals,
+    ArnLike,
+}
+
+// Returns the base operator and whether the result is negated.
+fn parse_operator(operator: &str) -> Option<(BaseOperator, bool)> {
+    use BaseOperator::*;
+    let parsed = match operator {
+        "StringEquals" => (StringEquals, false),
+        "StringNotEquals" => (StringEquals, true),
+        "StringEqualsIgnoreCase" => (StringEqualsIgnoreCase, false),
+        "StringNotEqualsIgnoreCase" => (StringEqualsIgnoreCase, true),
+        "StringLike" => (StringLike, false),
+        "StringNotLike" => (StringLike, true),
+        "NumericEquals" => (NumericEquals, false),
+        "NumericNotEquals" => (NumericEquals, true),
+        "NumericLessThan" => (NumericLessThan, false),
+        "NumericLessThanEquals" => (NumericLessThanEquals, false),
+        "NumericGreaterThan" => (NumericGreaterThan, false),
+        "NumericGreaterThanEquals" => (NumericGreaterThanEquals, false),
+        "DateEquals" => (DateEquals, false),
+        "DateNotEquals" => (DateEquals, true),
+        "DateLessThan" => (DateLessThan, false),
+        "DateLessThanEquals" => (DateLessThanEquals, false),
+        "DateGreaterThan" => (DateGreaterThan, false),
+        "DateGreaterThanEquals" => (DateGreaterThanEquals, false),
+        "Bool" => (Bool, false),
+        "BinaryEquals" => (BinaryEquals, false),
+        "IpAddress" => (IpAddress, false),
+        "NotIpAddress" => (IpAddress, true),
+        "ArnEquals" => (ArnEquals, false),
+        "ArnNotEquals" => (ArnEquals, true),
+        "ArnLike" => (ArnLike, false),
+        "ArnNotLike" => (ArnLike, true),
+        _ => return None,
+    };
+    Some(parsed)
+}
+
+// A condition value with its policy variables resolved.
+struct ExpectedValue<'a> {
+    text: Cow<'a, str>,
+    /// Only set for values that had variables.
+    pattern: Option<ResolvedPattern>,
+}
+
+fn resolve_values<'a>(
+    expected: &'a [String],
+    context: &RequestContext,
+    variables: bool,
+) -> Result<Vec<ExpectedValue<'a>>, Vec<String>> {
+    let mut unresolved = vec![];
+    let mut values = vec![];
+    for value in expected {
+        if !variables || !has_variables(value) {
+            values.push(ExpectedValue {
+                text: Cow::Borrowed(value),
+                pattern: None,
+            });
+            continue;
+        }
+        match resolve(value, context) {
+            Ok(pattern) => values.push(ExpectedValue {
+                text: Cow::Owned(resolved_text(&pattern)),
+                pattern: Some(pattern),
+            }),
+            Err(mut keys) => unresolved.append(&mut keys),
+        }
+    }
+    if unresolved.is_empty() {
+        Ok(values)
+    } else {
+        Err(unresolved)
+    }
+}
+
+fn base_matches(operator: BaseOperator, expected: &ExpectedValue, actual: &str) -> bool {
+    use BaseOperator::*;
+    let (pattern, expected) = (&expected.pattern, expected.text.as_ref());
+    match operator {
+        StringEquals | BinaryEquals => expected == actual,
+        StringEqualsIgnoreCase => expected.eq_ignore_ascii_case(actual),
+        StringLike => match pattern {
+            Some(pattern) => Glob::resolved(pattern).is_match(actual),
+            None => glob_matches(expected, actual),
+        },
+        NumericEquals => compare_numbers(expected, actual, |a, e| a == e),
+        NumericLessThan => compare_numbers(expected, actual, |a, e| a < e),
+        NumericLessThanEquals => compare_numbers(expected, actual, |a, e| a <= e),
+        NumericGreaterThan => compare_numbers(expected, actual, |a, e| a > e),
+        NumericGreaterThanEquals => compare_numbers(expected, actual, |a, e| a >= e),
+        DateEquals => compare_dates(expected, actual, |a, e| a == e),
+        DateLessThan => compare_dates(expected, actual, |a, e| a < e),
+        DateLessThanEquals => compare_dates(expected, actual, |a, e| a <= e),
+        DateGreaterThan => compare_dates(expected, actual, |a, e| a > e),
+        DateGreaterThanEquals => compare_dates(expected, actual, |a, e| a >= e),
+        Bool => expected.eq_ignore_ascii_case(actual),
+        IpAddress => ip_in_cidr(actual, expected),
+        ArnEquals | ArnLike => match pattern {
+            Some(pattern) => ArnPattern::resolved(pattern).is_match(actual),
+            None => arn_matches(expected, actual),
+        },
+    }
+}
+
+fn compare_numbers(expected: &str, actual: &str, cmp: fn(f64, f64) -> bool) -> bool {
+    match (actual.parse::<f64>(), expected.parse::<f64>()) {
+        (Ok(a), Ok(e)) => cmp(a, e),
+        _ => false,
+    }
+}
+
+fn compare_dates(expected: &str, actual: &str, cmp: fn(f64, f64) -> bool) -> bool {
+    match (parse_date(actual), parse_date(expected)) {
+        (Some(a), Some(e)) => cmp(a, e),
+        _ => false,
+    }
+}
+
+// Dates are either epoch seconds or ISO 8601 (`2024-01-31`, `2024-01-31T12:00:00Z`,
+// `2024-01-31T12:00:00.123+09:00`). Returns seconds since the epoch.
+fn parse_date(s: &str) -> Option<f64> {
+    if let Ok(epoch) = s.parse::<f64>() {
+        return Some(epoch);
+    }
+
+    let (date, time) = match s.split_once('T') {
+        Some((d, t)) => (d, Some(t)),
+        None => (s, None),
+    };
+
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut seconds = (days_from_civil(year, month, day) * 86_400) as f64;
+
+    if let Some(time) = time {
+        let (clock, offset) = if let Some(clock) = time.strip_suffix('Z') {
+            (clock, 0)
+        } else if let Some(idx) = time.rfind(['+', '-']) {
+            let sign = if time[idx..].starts_with('-') { -1 } else { 1 };
+            let (h, m) = time[idx + 1..].split_once(':').unwrap_or((&time[idx + 1..], "0"));
+            let offset = h.parse::<i64>().ok()? * 3600 + m.parse::<i64>().ok()? * 60;
+            (&time[..idx], sign * offset)
+        } else {
+            (time, 0)
+        };
+
+        let mut clock_parts = clock.splitn(3, ':');
+        let hour: f64 = clock_parts.next()?.parse().ok()?;
+        let minute: f64 = clock_parts.next().unwrap_or("0").parse().ok()?;
+        let second: f64 = clock_parts.next().unwrap_or("0").parse().ok()?;
+        seconds += hour * 3600.0 + minute * 60.0 + second - offset as f64;
+    }
+
+    Some(seconds)
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn ip_in_cidr(ip: &str, cidr: &str) -> bool {
+    let (network, prefix) = match cidr.split_once('/') {
+        Some((n, p)) => (n, p.parse::<u32>().ok()),
+        None => (cidr, None),
+    };
+    let (Ok(ip), Ok(network)) = (ip.parse::<IpAddr>(), network.parse::<IpAddr>()) else {
+        return false;
+    };
+
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let prefix = prefix.unwrap_or(32).min(32);
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let prefix = prefix.unwrap_or(128).min(128);
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+fn to_result(matched: bool) -> ConditionResult {
+    if matched {
+        ConditionResult::Match
+    } else {
+        ConditionResult::NoMatch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn condition(operator: &str, key: &str, values: &[&str]) -> Condition {
+        let values = values.iter().map(|v| v.to_string()).collect();
+        Condition::from([(operator.to_string(), [(key.to_string(), values)].into())])
+    }
+
+    fn context(pairs: &[&str]) -> RequestContext {
+        let pairs: Vec<String> = pairs.iter().map(|p| p.to_string()).collect();
+        RequestContext::from_pairs(&pairs).expect("valid pairs")
+    }
+
+    fn check(operator: &str, key: &str, values: &[&str], pairs: &[&str]) -> ConditionResult {
+        evaluate_condition(&condition(operator, key, values), &context(pairs), true)
+    }
+
+    #[test]
+    fn string_and_bool_operators() {
+        use ConditionResult::*;
+        assert_eq!(
+            check(
+                "StringEquals",
+                "aws:RequestedRegion",
+                &["eu-west-1"],
+                &["aws:RequestedRegion=eu-west-1"]
+            ),
+            Match
+        );
+        assert_eq!(
+            check(
+                "StringEquals",
+                "aws:RequestedRegion",
+                &["EU-WEST-1"],
+                &["aws:RequestedRegion=eu-west-1"]
+            ),
+            NoMatch
+        );
+        assert_eq!(
+            check(
+                "StringEqualsIgnoreCase",
+                "aws:RequestedRegion",
+                &["EU-WEST-1"],
+                &["aws:RequestedRegion=eu-west-1"]
+            ),
+            Match
+        );
+        assert_eq!(
+            check(
+                "StringNotEquals",
+                "aws:RequestedRegion",
+                &["us-east-1", "eu-west-1"],
+                &["aws:RequestedRegion=eu-west-1"]
+            ),
+            NoMatch
+        );
+        assert_eq!(
+            check("StringLike", "s3:prefix", &["home/*"], &["s3:prefix=home/alice"]),
+            Match
+        );
+        assert_eq!(
+            check("StringNotLike", "s3:prefix", &["home/*"], &["s3:prefix=tmp/alice"]),
+            Match
+        );
+        assert_eq!(
+            check("Bool", "aws:SecureTransport", &["true"], &["aws:SecureTransport=TRUE"]),
+            Match
+        );
+        assert_eq!(
+            check("NumericLessThan", "s3:max-keys", &["10"], &["s3:max-keys=5"]),
+            Match
+        );
+        assert_eq!(
+            check("NumericGreaterThanEquals", "s3:max-keys", &["10"], &["s3:max-keys=abc"]),
+            NoMatch
+        );
+        assert_eq!(
+            check(
+                "ArnLike",
+                "aws:SourceArn",
+                &["arn:aws:sns:*:123456789012:*"],
+                &["aws:SourceArn=arn:aws:sns:eu-west-1:123456789012:topic"]
+            ),
+            Match
+        );
+        assert_eq!(
+            check("NoSuchOperator", "aws:SourceArn", &["x"], &["aws:SourceArn=x"]),
+            NoMatch
+        );
+    }
+
+    #[test]
+    fn keys_are_case_insensitive() {
+        assert_eq!(
+            check(
+                "StringEquals",
+                "AWS:RequestedRegion",
+                &["eu-west-1"],
+                &["aws:requestedregion=eu-west-1"]
+            ),
+            ConditionResult::Match
+        );
+    }
+
+    #[test]
+    fn missing_keys_are_reported_unless_if_exists() {
+        assert_eq!(
+            check("StringEquals", "aws:SourceVpc", &["vpc-1"], &[]),
+            ConditionResult::MissingKeys(vec!["aws:SourceVpc".to_string()])
+        );
+        assert_eq!(
+            check("StringEqualsIfExists", "aws:SourceVpc", &["vpc-1"], &[]),
+            ConditionResult::Match
+        );
+        assert_eq!(
+            check(
+                "StringEqualsIfExists",
+                "aws:SourceVpc",
+                &["vpc-1"],
+                &["aws:SourceVpc=vpc-2"]
+            ),
+            ConditionResult::NoMatch
+        );
+    }
+
+    #[test]
+    fn set_qualifiers() {
+        use ConditionResult::*;
+        let tags = ["aws:TagKeys=team", "aws:TagKeys=env"];
+        assert_eq!(
+            check(
+                "ForAllValues:StringEquals",
+                "aws:TagKeys",
+                &["team", "env", "owner"],
+                &tags
+            ),
+            Match
+        );
+        assert_eq!(
+            check("ForAllValues:StringEquals", "aws:TagKeys", &["team"], &tags),
+            NoMatch
+        );
+        assert_eq!(check("ForAnyValue:StringEquals", "aws:TagKeys", &["env"], &tags), Match);
+        assert_eq!(
+            check("ForAnyValue:StringEquals", "aws:TagKeys", &["owner"], &tags),
+            NoMatch
+        );
+        // A negated operator without a qualifier must hold for every value.
+        assert_eq!(check("StringNotEquals", "aws:TagKeys", &["env"], &tags), NoMatch);
+    }
+
+    #[test]
+    fn null_checks_presence() {
+        use ConditionResult::*;
+        assert_eq!(check("Null", "aws:TokenIssueTime", &["true"], &[]), Match);
+        assert_eq!(
+            check(
+                "Null",
+                "aws:TokenIssueTime",
+                &["true"],
+                &["aws:TokenIssueTime=2024-01-31"]
+            ),
+            NoMatch
+        );
+        assert_eq!(
+            check(
+                "Null",
+                "aws:TokenIssueTime",
+                &["false"],
+                &["aws:TokenIssueTime=2024-01-31"]
+            ),
+            Match
+        );
+        assert_eq!(check("Null", "aws:TokenIssueTime", &["false"], &[]), NoMatch);
+    }
+
+    #[test]
+    fn every_operator_must_match() {
+        let mut both = condition("Bool", "aws:SecureTransport", &["true"]);
+        both.extend(condition("StringEquals", "aws:RequestedRegion", &["eu-west-1"]));
+        let result = evaluate_condition(&both, &context(&["aws:SecureTransport=true"]), true);
+        assert_eq!(
+            result,
+            ConditionResult::MissingKeys(vec!["aws:RequestedRegion".to_string()])
+        );
+        let result = evaluate_condition(&both, &context(&["aws:SecureTransport=false"]), true);
+        assert_eq!(result, ConditionResult::NoMatch);
+    }
+
+    #[test]
+    fn ip_addresses_in_cidr_ranges() {
+        assert!(ip_in_cidr("10.1.2.3", "10.0.0.0/8"));
+        assert!(!ip_in_cidr("11.0.0.1", "10.0.0.0/8"));
+        assert!(ip_in_cidr("203.0.113.10", "203.0.113.10"));
+        assert!(!ip_in_cidr("203.0.113.11", "203.0.113.10"));
+        assert!(ip_in_cidr("198.51.100.7", "0.0.0.0/0"));
+        assert!(ip_in_cidr("2001:db8::1", "2001:db8::/32"));
+        assert!(!ip_in_cidr("2001:db9::1", "2001:db8::/32"));
+        assert!(!ip_in_cidr("10.1.2.3", "2001:db8::/32"));
+        assert!(!ip_in_cidr("not-an-ip", "10.0.0.0/8"));
+        assert_eq!(
+            check(
+                "NotIpAddress",
+                "aws:SourceIp",
+                &["10.0.0.0/8"],
+                &["aws:SourceIp=192.0.2.1"]
+            ),
+            ConditionResult::Match
+        );
+    }
+
+    #[test]
+    fn dates_in_every_accepted_format() {
+        assert_eq!(parse_date("1970-01-01"), Some(0.0));
+        assert_eq!(parse_date("2024-01-31"), Some(1_706_659_200.0));
+        assert_eq!(parse_date("2024-01-31T12:00:00Z"), Some(1_706_702_400.0));
+        assert_eq!(parse_date("2024-01-31T21:00:00+09:00"), Some(1_706_702_400.0));
+        assert_eq!(parse_date("2024-01-31T07:00:00-05:00"), Some(1_706_702_400.0));
+        assert_eq!(parse_date("2024-01-31T12:00:00.5Z"), Some(1_706_702_400.5));
+        assert_eq!(parse_date("1706702400"), Some(1_706_702_400.0));
+        assert_eq!(parse_date("2024-13-01"), None);
+        assert_eq!(parse_date("yesterday"), None);
+        assert_eq!(
+            check(
+                "DateLessThan",
+                "aws:CurrentTime",
+                &["2025-01-01T00:00:00Z"],
+                &["aws:CurrentTime=2024-06-01"]
+            ),
+            ConditionResult::Match
+        );
+    }
+
+    #[test]
+    fn variables_resolve_only_when_supported() {
+        let pairs = ["aws:username=alice", "s3:prefix=home/alice/notes"];
+        let home = condition("StringLike", "s3:prefix", &["home/${aws:username}/*"]);
+        assert_eq!(evaluate_condition(&home, &context(&pairs), true), ConditionResult::Match);
+        assert_eq!(evaluate_condition(&home, &context(&pairs), false), ConditionResult::NoMatch);
+        assert_eq!(
+            evaluate_condition(&home, &context(&["s3:prefix=home/bob/notes", "aws:username=alice"]), true),
+            ConditionResult::NoMatch
+        );
+        assert_eq!(
+            evaluate_condition(&home, &context(&["s3:prefix=home/alice/notes"]), true),
+            ConditionResult::MissingKeys(vec!["aws:username".to_string()])
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use crate::catalog::ActionCatalog;
+use crate::effective::list_allowed_actions;
+use crate::evaluation::{evaluate_entity, LayeredEvaluation, Request};
+use crate::resource_policy::ResourcePolicy;
+use crate::scp::ScpLevel;
+use crate::source::EntityPolicies;
+use std::collections::HashSet;
+
+/// An action the two entities get different decisions for.
+#[derive(Debug, Clone)]
+pub struct ActionDiff {
+    pub action: String,
+    pub left: LayeredEvaluation,
+    pub right: LayeredEvaluation,
+}
+
+// Without `action_names`, every action either entity is allowed is compared,
+// expanded against the catalog like `list_allowed_actions`.
+pub fn diff_entities(
+    left: &EntityPolicies,
+    right: &EntityPolicies,
+    scp_levels: &[ScpLevel],
+    resource_policy: Option<&ResourcePolicy>,
+    request: &Request,
+    action_names: &[String],
+    catalog: &ActionCatalog,
+) -> Vec<ActionDiff> {
+    let candidates: Vec<String> = if action_names.is_empty() {
+        let mut seen = HashSet::new();
+        let mut candidates: Vec<String> = [left, right]
+            .into_iter()
+            .flat_map(|policies| list_allowed_actions(policies, scp_levels, resource_policy, request, catalog))
+            .map(|allowed| allowed.action)
+            .filter(|action| seen.insert(action.to_lowercase()))
+            .collect();
+        candidates.sort_by_key(|a| a.to_lowercase());
+        candidates
+    } else {
+        action_names.to_vec()
+    };
+
+    candidates
+        .into_iter()
+        .filter_map(|action| {
+            let request = Request {
+                action_name: action.clone(),
+                ..request.clone()
+            };
+            let left = evaluate_entity(left, scp_levels, resource_policy, &request);
+            let right = evaluate_entity(right, scp_levels, resource_policy, &request);
+            (left.decision != right.decision).then_some(ActionDiff { action, left, right })
+        })
+        .collect()
+}
@@ -0,0 +1,186 @@
+use crate::catalog::ActionCatalog;
+use crate::evaluation::{evaluate_entity, Decision, Request};
+use crate::policy::{Effect, Statement};
+use crate::resource_policy::ResourcePolicy;
+use crate::scp::ScpLevel;
+use crate::source::EntityPolicies;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Stands for every action of the services missing from the catalog, which
+/// an Allow statement with `NotAction` grants without naming them.
+pub const UNLISTED_ACTIONS: &str = "*";
+
+/// An action the entity may perform, with the statement that grants it.
+#[derive(Debug, Clone)]
+pub struct AllowedAction {
+    pub action: String,
+    /// `Allowed`, or `Conditional` when it depends on missing context keys.
+    pub decision: Decision,
+    pub policy_name: String,
+    pub statement: Option<Statement>,
+    pub expansion: Expansion,
+    /// The `NotAction` entries of the statement granting `UNLISTED_ACTIONS`.
+    pub excludes: Vec<String>,
+}
+
+/// How far the listing of an action can be trusted to be complete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Expansion {
+    /// A catalog action, named by the statement or expanded from a service
+    /// the catalog lists completely.
+    Complete,
+    /// A catalog action matched by a wildcard or `NotAction` over a service
+    /// the catalog only lists in part. The statement may allow more actions
+    /// of the service than are listed.
+    Truncated,
+    /// An action or wildcard of a service missing from the catalog, or
+    /// `UNLISTED_ACTIONS`, listed as written.
+    NotExpanded,
+}
+
+// Every catalog action, plus the actions and wildcards of unknown services
+// named in Allow statements, is evaluated like a single `--action-name`
+// check, so denies, boundaries and SCPs are subtracted the same way. Allow
+// statements with `NotAction` add `UNLISTED_ACTIONS` for the services they
+// grant without naming.
+pub fn list_allowed_actions(
+    policies: &EntityPolicies,
+    scp_levels: &[ScpLevel],
+    resource_policy: Option<&ResourcePolicy>,
+    request: &Request,
+    catalog: &ActionCatalog,
+) -> Vec<AllowedAction> {
+    let mut candidates: Vec<(String, bool)> = catalog.actions().map(|a| (a, true)).collect();
+    let mut known: HashSet<String> = candidates.iter().map(|(a, _)| a.to_lowercase()).collect();
+
+    let resource_statements = resource_policy
+        .map(|p| p.document.statement.iter())
+        .into_iter()
+        .flatten();
+    let allow_statements = policies
+        .identity
+        .iter()
+        .flat_map(|(_, document)| document.statement.iter())
+        .chain(resource_statements)
+        .filter(|statement| statement.effect == Effect::Allow);
+    for statement in allow_statements {
+        if statement.not_action.is_some() && known.insert(UNLISTED_ACTIONS.to_string()) {
+            candidates.push((UNLISTED_ACTIONS.to_string(), false));
+        }
+        for action in statement.action.iter().flatten() {
+            let prefix = action.split(':').next().unwrap_or_default();
+            let is_wildcard = action.contains(['*', '?']);
+            if is_wildcard && catalog.service(prefix).is_some() {
+                continue;
+            }
+            if known.insert(action.to_lowercase()) {
+                candidates.push((action.clone(), false));
+            }
+        }
+    }
+
+    let mut allowed: Vec<AllowedAction> = candidates
+        .into_iter()
+        .filter_map(|(action, in_catalog)| {
+            let request = Request {
+                action_name: action.clone(),
+                ..request.clone()
+            };
+            let evaluation = evaluate_entity(policies, scp_levels, resource_policy, &request);
+            if !matches!(evaluation.decision, Decision::Allowed | Decision::Conditional) {
+                return None;
+            }
+            let deciding = evaluation.deciding_statement();
+            let statement = deciding.map(|d| &d.statement);
+            let expansion = match statement {
+                _ if !in_catalog => Expansion::NotExpanded,
+                Some(statement) if expanded_from_partial_service(&action, statement, catalog) => {
+                    Expansion::Truncated
+                }
+                _ => Expansion::Complete,
+            };
+            let excludes = match statement {
+                Some(statement) if action == UNLISTED_ACTIONS => statement.not_action.clone().unwrap_or_default(),
+                _ => vec![],
+            };
+            Some(AllowedAction {
+                decision: evaluation.decision,
+                policy_name: deciding.map(|d| d.policy_name.clone()).unwrap_or_default(),
+                statement: statement.cloned(),
+                expansion,
+                excludes,
+                action,
+            })
+        })
+        .collect();
+
+    allowed.sort_by_key(|a| a.action.to_lowercase());
+    allowed
+}
+
+// Whether `statement` grants the catalog action `action` through a wildcard
+// or `NotAction` rather than by name, over a service the catalog does not
+// list completely.
+fn expanded_from_partial_service(action: &str, statement: &Statement, catalog: &ActionCatalog) -> bool {
+    let named = statement
+        .action
+        .iter()
+        .flatten()
+        .any(|a| a.eq_ignore_ascii_case(action));
+    let complete = catalog.action(action).is_some_and(|(service, _)| service.complete);
+    !named && !complete
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{policy, request};
+
+    fn list(statements: &str) -> Vec<AllowedAction> {
+        let policies = EntityPolicies {
+            identity: vec![("policy".to_string(), policy(statements))],
+            ..Default::default()
+        };
+        let catalog = ActionCatalog::bundled();
+        list_allowed_actions(&policies, &[], None, &request("*", None), catalog)
+    }
+
+    fn find<'a>(allowed: &'a [AllowedAction], action: &str) -> Option<&'a AllowedAction> {
+        allowed.iter().find(|a| a.action == action)
+    }
+
+    #[test]
+    fn not_action_lists_unlisted_services_with_exclusions() {
+        let allowed = list(r#"[{"Effect":"Allow","NotAction":["iam:*","organizations:*"],"Resource":"*"}]"#);
+
+        let unlisted = find(&allowed, UNLISTED_ACTIONS).expect("services outside the catalog are listed");
+        assert_eq!(unlisted.expansion, Expansion::NotExpanded);
+        assert_eq!(unlisted.excludes, ["iam:*", "organizations:*"]);
+
+        let run_instances = find(&allowed, "ec2:RunInstances").expect("ec2 is granted");
+        assert_eq!(run_instances.expansion, Expansion::Truncated);
+        assert!(allowed.iter().all(|a| !a.action.starts_with("iam:")));
+    }
+
+    #[test]
+    fn wildcards_over_partial_services_are_truncated() {
+        let allowed = list(
+            r#"[
+                {"Effect":"Allow","Action":"ec2:*","Resource":"*"},
+                {"Effect":"Allow","Action":["s3:GetObject","billing:ViewBilling"],"Resource":"*"}
+            ]"#,
+        );
+
+        let ec2: Vec<&AllowedAction> = allowed.iter().filter(|a| a.action.starts_with("ec2:")).collect();
+        assert!(!ec2.is_empty());
+        assert!(ec2.iter().all(|a| a.expansion == Expansion::Truncated));
+        assert_eq!(find(&allowed, "s3:GetObject").map(|a| a.expansion), Some(Expansion::Complete));
+        assert_eq!(
+            find(&allowed, "billing:ViewBilling").map(|a| a.expansion),
+            Some(Expansion::NotExpanded)
+        );
+        assert!(find(&allowed, UNLISTED_ACTIONS).is_none());
+    }
+}
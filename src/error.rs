@@ -0,0 +1,116 @@
+use crate::source::Entity;
+use aws_sdk_iam::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
+use std::fmt::Display;
+
+/// Everything that can go wrong while reading IAM data and policies.
+#[derive(Debug)]
+pub enum Error {
+    /// The credentials are not allowed to call `operation`, e.g. `iam:ListRolePolicies`.
+    MissingPermission {
+        operation: &'static str,
+        message: Option<String>,
+    },
+    EntityNotFound(Entity),
+    /// A managed policy, by ARN, or an inline policy, by name.
+    PolicyNotFound(String),
+    PolicyParse {
+        policy: String,
+        message: String,
+    },
+    Throttled {
+        operation: &'static str,
+    },
+    /// Any other failure of an AWS API call.
+    Sdk {
+        operation: &'static str,
+        message: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    Other(String),
+}
+
+impl Error {
+    /// Classifies a failed call to `operation`. `entity` is what the call was
+    /// about, and is reported as not found when the service says so.
+    pub fn from_sdk<E>(operation: &'static str, entity: Option<Entity>, err: SdkError<E>) -> Self
+    where
+        E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+    {
+        let code = err.code().map(|c| c.to_string());
+        match (code.as_deref(), entity) {
+            (Some("AccessDenied" | "AccessDeniedException" | "AuthorizationError" | "UnauthorizedOperation"), _) => {
+                Error::MissingPermission {
+                    operation,
+                    message: err.message().map(|m| m.to_string()),
+                }
+            }
+            (Some("NoSuchEntity"), Some(entity)) => Error::EntityNotFound(entity),
+            (Some("Throttling" | "ThrottlingException" | "TooManyRequestsException"), _) => {
+                Error::Throttled { operation }
+            }
+            _ => Error::Sdk {
+                operation,
+                message: describe_sdk_error(&err),
+                source: Box::new(err),
+            },
+        }
+    }
+}
+
+// A one-line description instead of the full chain of nested errors.
+fn describe_sdk_error<E>(err: &SdkError<E>) -> String
+where
+    E: ProvideErrorMetadata + std::error::Error + 'static,
+{
+    match err {
+        SdkError::ServiceError(_) => match (err.code(), err.message()) {
+            (Some(code), Some(message)) => format!("{}: {}", code, message),
+            (Some(code), None) => code.to_string(),
+            _ => DisplayErrorContext(err).to_string(),
+        },
+        SdkError::DispatchFailure(_) => {
+            let mut cause: &dyn std::error::Error = err;
+            while let Some(source) = cause.source() {
+                cause = source;
+            }
+            format!("could not reach AWS: {}", cause)
+        }
+        SdkError::TimeoutError(_) => "the request timed out".to_string(),
+        _ => DisplayErrorContext(err).to_string(),
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::MissingPermission { operation, .. } => write!(f, "your credentials lack {}", operation),
+            Error::EntityNotFound(entity) => write!(f, "{} not found", entity),
+            Error::PolicyNotFound(policy) => write!(f, "policy {} not found", policy),
+            Error::PolicyParse { policy, message } => write!(f, "could not parse policy {}: {}", policy, message),
+            Error::Throttled { operation } => write!(f, "{} was throttled by AWS, try again later", operation),
+            Error::Sdk { operation, message, .. } => write!(f, "{} failed: {}", operation, message),
+            Error::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Sdk { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Other(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::Other(message.to_string())
+    }
+}
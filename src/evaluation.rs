@@ -0,0 +1,459 @@
+use crate::arn::check_resource_in_statement;
+use crate::condition::{evaluate_condition, ConditionResult, RequestContext};
+use crate::policy::{Effect, PolicyDocument, Statement};
+use crate::resource_policy::{account_of, ResourcePolicy};
+use crate::scp::ScpLevel;
+use crate::source::EntityPolicies;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt::Display;
+
+/// A policy name paired with its parsed document.
+pub type NamedPolicy = (String, PolicyDocument);
+
+/// Final verdict for an action, following IAM's "explicit deny wins" rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Decision {
+    ExplicitDeny,
+    Allowed,
+    /// The verdict depends on condition keys missing from the request context.
+    Conditional,
+    ImplicitDeny,
+}
+
+impl Display for Decision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Decision::ExplicitDeny => write!(f, "ExplicitDeny"),
+            Decision::Allowed => write!(f, "Allowed"),
+            Decision::Conditional => write!(f, "Conditional"),
+            Decision::ImplicitDeny => write!(f, "ImplicitDeny"),
+        }
+    }
+}
+
+/// A set of policies evaluated together as one step of IAM's evaluation logic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyLayer {
+    Identity,
+    ResourcePolicy,
+    PermissionsBoundary,
+    SessionPolicy,
+    ServiceControlPolicy,
+}
+
+impl Display for PolicyLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            PolicyLayer::Identity => write!(f, "identity policies"),
+            PolicyLayer::ResourcePolicy => write!(f, "resource policy"),
+            PolicyLayer::PermissionsBoundary => write!(f, "permissions boundary"),
+            PolicyLayer::SessionPolicy => write!(f, "session policies"),
+            PolicyLayer::ServiceControlPolicy => write!(f, "service control policies"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub action_name: String,
+    /// When absent, statements are matched on their action only.
+    pub resource_arn: Option<String>,
+    pub context: RequestContext,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchedStatement {
+    pub policy_name: String,
+    /// Position of the policy among those the layer evaluated.
+    pub policy_index: usize,
+    pub statement: Statement,
+    /// Condition keys the statement needs but the request context lacks.
+    pub missing_context_keys: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    pub decision: Decision,
+    pub allowed: Vec<MatchedStatement>,
+    pub denied: Vec<MatchedStatement>,
+    /// Statements whose conditions could not be decided, of either effect.
+    pub conditional: Vec<MatchedStatement>,
+}
+
+impl Evaluation {
+    /// The statement that decided the verdict, if any.
+    /// An implicit deny has no deciding statement.
+    pub fn deciding_statement(&self) -> Option<&MatchedStatement> {
+        match self.decision {
+            Decision::ExplicitDeny => self.denied.first(),
+            Decision::Allowed => self.allowed.first(),
+            Decision::Conditional => self.conditional.first(),
+            Decision::ImplicitDeny => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LayerEvaluation {
+    pub layer: PolicyLayer,
+    /// Describes where the policies come from, e.g. the OU of a set of SCPs.
+    pub name: String,
+    pub policy_names: Vec<String>,
+    pub evaluation: Evaluation,
+    /// Whether the layer has to allow the action. Within an account, a
+    /// resource policy and identity policies grant access independently,
+    /// so only one of them is required. Explicit denies always count.
+    pub required: bool,
+}
+
+impl LayerEvaluation {
+    pub fn evaluate(
+        layer: PolicyLayer,
+        name: String,
+        policies: &[NamedPolicy],
+        request: &Request,
+    ) -> Self {
+        LayerEvaluation {
+            layer,
+            name,
+            policy_names: policies.iter().map(|(name, _)| name.clone()).collect(),
+            evaluation: evaluate(policies, request),
+            required: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LayeredEvaluation {
+    pub decision: Decision,
+    pub layers: Vec<LayerEvaluation>,
+    /// Index into `layers` of the layer that denied, or failed to allow, the action.
+    pub blocking: Option<usize>,
+}
+
+impl LayeredEvaluation {
+    pub fn blocking_layer(&self) -> Option<&LayerEvaluation> {
+        self.layers.get(self.blocking?)
+    }
+
+    /// The statement that decided the verdict, taken from the blocking layer
+    /// when there is one, and from the policies that granted access otherwise.
+    pub fn deciding_statement(&self) -> Option<&MatchedStatement> {
+        let layer = match self.blocking_layer() {
+            Some(layer) => layer,
+            None => self.layers.iter().find(|l| {
+                l.required && matches!(l.layer, PolicyLayer::Identity | PolicyLayer::ResourcePolicy)
+            })?,
+        };
+        layer.evaluation.deciding_statement()
+    }
+}
+
+// Every required layer has to allow the action: an explicit deny anywhere wins,
+// and a layer that does not allow it (a boundary, for example) caps the rest.
+// Like an explicit deny, a Deny that may apply counts in any layer.
+pub fn evaluate_layers(layers: Vec<LayerEvaluation>) -> LayeredEvaluation {
+    let find = |decision: Decision| {
+        layers
+            .iter()
+            .position(|l| l.evaluation.decision == decision && (l.required || decision == Decision::ExplicitDeny))
+    };
+    let conditionally_denied = |l: &LayerEvaluation| {
+        l.evaluation
+            .conditional
+            .iter()
+            .any(|m| m.statement.effect == Effect::Deny)
+    };
+
+    let (decision, blocking) = if let Some(index) = find(Decision::ExplicitDeny) {
+        (Decision::ExplicitDeny, Some(index))
+    } else if let Some(index) = find(Decision::ImplicitDeny) {
+        (Decision::ImplicitDeny, Some(index))
+    } else if let Some(index) = layers.iter().position(|l| {
+        (l.required && l.evaluation.decision == Decision::Conditional) || conditionally_denied(l)
+    }) {
+        (Decision::Conditional, Some(index))
+    } else {
+        (Decision::Allowed, None)
+    };
+
+    LayeredEvaluation {
+        decision,
+        layers,
+        blocking,
+    }
+}
+
+// Identity policies and the resource policy, then the permissions boundary,
+// then the session policies, then each SCP level.
+pub fn evaluate_entity(
+    policies: &EntityPolicies,
+    scp_levels: &[ScpLevel],
+    resource_policy: Option<&ResourcePolicy>,
+    request: &Request,
+) -> LayeredEvaluation {
+    // The entity's own keys, like `aws:username`, unless the request sets them.
+    let mut request = request.clone();
+    request.context.merge_defaults(&policies.principal_context);
+    let request = &request;
+
+    let mut identity = LayerEvaluation::evaluate(
+        PolicyLayer::Identity,
+        PolicyLayer::Identity.to_string(),
+        &policies.identity,
+        request,
+    );
+    let resource = resource_policy
+        .map(|resource_policy| evaluate_resource_policy(&mut identity, resource_policy, policies, request));
+    let mut layers = vec![identity];
+    layers.extend(resource);
+
+    if let Some(boundary) = &policies.permissions_boundary {
+        let mut boundary = LayerEvaluation::evaluate(
+            PolicyLayer::PermissionsBoundary,
+            PolicyLayer::PermissionsBoundary.to_string(),
+            std::slice::from_ref(boundary),
+            request,
+        );
+        // A resource policy that names an IAM user within the account is not
+        // capped by the user's boundary. Role sessions still are.
+        let is_user = policies.arn.as_deref().is_some_and(|arn| arn.contains(":user/"));
+        if is_user && !layers[0].required {
+            boundary.required = false;
+        }
+        layers.push(boundary);
+    }
+
+    // Session policies are evaluated together, and cap access granted through
+    // a resource policy too, since it names the role rather than the session.
+    if !policies.session_policies.is_empty() {
+        layers.push(LayerEvaluation::evaluate(
+            PolicyLayer::SessionPolicy,
+            PolicyLayer::SessionPolicy.to_string(),
+            &policies.session_policies,
+            request,
+        ));
+    }
+
+    for level in scp_levels {
+        layers.push(LayerEvaluation::evaluate(
+            PolicyLayer::ServiceControlPolicy,
+            format!("{} at {}", PolicyLayer::ServiceControlPolicy, level.name),
+            &level.policies,
+            request,
+        ));
+    }
+
+    evaluate_layers(layers)
+}
+
+// Within one account, either the identity policies or a resource policy that
+// names the entity grants access. A resource policy that only names the
+// account delegates to identity policies, and KMS key policies always have to
+// allow. Across accounts, both sides have to allow.
+fn evaluate_resource_policy(
+    identity: &mut LayerEvaluation,
+    resource_policy: &ResourcePolicy,
+    policies: &EntityPolicies,
+    request: &Request,
+) -> LayerEvaluation {
+    let principal_arn = policies.arn.as_deref();
+    let entity_account = principal_arn.and_then(account_of);
+    let resource_account = resource_policy
+        .account
+        .as_deref()
+        .or_else(|| request.resource_arn.as_deref().and_then(account_of));
+    // Without both accounts known, the resource is assumed to be in the entity's account.
+    let same_account = match (entity_account, resource_account) {
+        (Some(entity), Some(resource)) => entity == resource,
+        _ => true,
+    };
+    let is_kms = request.action_name.to_lowercase().starts_with("kms:");
+
+    if !same_account {
+        return resource_policy.evaluate(principal_arn, request, true);
+    }
+
+    if is_kms {
+        let direct = resource_policy.evaluate(principal_arn, request, false);
+        if direct.evaluation.decision == Decision::Allowed {
+            identity.required = false;
+            return direct;
+        }
+        return resource_policy.evaluate(principal_arn, request, true);
+    }
+
+    let mut direct = resource_policy.evaluate(principal_arn, request, false);
+    let grant_rank = |decision: Decision| match decision {
+        Decision::Allowed => 2,
+        Decision::Conditional => 1,
+        _ => 0,
+    };
+    if grant_rank(direct.evaluation.decision) > grant_rank(identity.evaluation.decision) {
+        identity.required = false;
+    } else {
+        direct.required = false;
+    }
+    direct
+}
+
+pub fn evaluate(policies: &[NamedPolicy], request: &Request) -> Evaluation {
+    let mut allowed = vec![];
+    let mut denied = vec![];
+    let mut conditional = vec![];
+
+    for (policy_index, (policy_name, policy)) in policies.iter().enumerate() {
+        for statement in &policy.statement {
+            let missing_context_keys = match statement_applies(statement, request) {
+                ConditionResult::Match => vec![],
+                ConditionResult::NoMatch => continue,
+                ConditionResult::MissingKeys(mut keys) => {
+                    // The same variable can appear in both Resource and Condition.
+                    let mut seen = HashSet::new();
+                    keys.retain(|key| seen.insert(key.to_lowercase()));
+                    keys
+                }
+            };
+            let matched = MatchedStatement {
+                policy_name: policy_name.clone(),
+                policy_index,
+                statement: statement.clone(),
+                missing_context_keys,
+            };
+            if !matched.missing_context_keys.is_empty() {
+                conditional.push(matched);
+                continue;
+            }
+            match statement.effect {
+                Effect::Allow => allowed.push(matched),
+                Effect::Deny => denied.push(matched),
+            }
+        }
+    }
+
+    let conditionally_allowed = conditional
+        .iter()
+        .any(|m| m.statement.effect == Effect::Allow);
+    let conditionally_denied = conditional
+        .iter()
+        .any(|m| m.statement.effect == Effect::Deny);
+
+    // A deny that may or may not apply leaves any allow undecided.
+    let decision = if !denied.is_empty() {
+        Decision::ExplicitDeny
+    } else if conditionally_denied && (!allowed.is_empty() || conditionally_allowed) {
+        Decision::Conditional
+    } else if !allowed.is_empty() {
+        Decision::Allowed
+    } else if conditionally_allowed {
+        Decision::Conditional
+    } else {
+        Decision::ImplicitDeny
+    };
+
+    Evaluation {
+        decision,
+        allowed,
+        denied,
+        conditional,
+    }
+}
+
+pub fn statement_applies(statement: &Statement, request: &Request) -> ConditionResult {
+    if !check_action_in_statement(statement, &request.action_name) {
+        return ConditionResult::NoMatch;
+    }
+    let resource = match &request.resource_arn {
+        Some(resource_arn) => check_resource_in_statement(statement, resource_arn, &request.context),
+        None => ConditionResult::Match,
+    };
+    let condition = match &statement.condition {
+        Some(condition) => evaluate_condition(condition, &request.context, statement.supports_variables()),
+        None => ConditionResult::Match,
+    };
+    match (resource, condition) {
+        (ConditionResult::NoMatch, _) | (_, ConditionResult::NoMatch) => ConditionResult::NoMatch,
+        (ConditionResult::MissingKeys(mut keys), ConditionResult::MissingKeys(mut more)) => {
+            keys.append(&mut more);
+            ConditionResult::MissingKeys(keys)
+        }
+        (ConditionResult::MissingKeys(keys), _) | (_, ConditionResult::MissingKeys(keys)) => {
+            ConditionResult::MissingKeys(keys)
+        }
+        _ => ConditionResult::Match,
+    }
+}
+
+// A statement applies to the action either through `Action`, or through
+// `NotAction`, which covers every action except the listed ones.
+pub fn check_action_in_statement(statement: &Statement, action_name: &str) -> bool {
+    let matches = statement
+        .action_patterns()
+        .iter()
+        .any(|pattern| pattern.is_match(action_name));
+
+    if statement.action.is_some() {
+        matches
+    } else {
+        statement.not_action.is_some() && !matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{policy, request};
+
+    #[test]
+    fn conditional_deny_in_resource_policy_counts_when_identity_allows() {
+        let policies = EntityPolicies {
+            arn: Some("arn:aws:iam::111122223333:user/alice".to_string()),
+            identity: vec![(
+                "s3".to_string(),
+                policy(r#"[{"Effect":"Allow","Action":"s3:*","Resource":"*"}]"#),
+            )],
+            ..Default::default()
+        };
+        let bucket_policy = ResourcePolicy {
+            name: "bucket-policy".to_string(),
+            document: policy(
+                r#"[
+                    {"Effect":"Allow","Principal":{"AWS":"arn:aws:iam::111122223333:user/alice"},"Action":"s3:GetObject","Resource":"arn:aws:s3:::bucket/*"},
+                    {"Effect":"Deny","Principal":"*","Action":"s3:*","Resource":"arn:aws:s3:::bucket/*","Condition":{"Bool":{"aws:SecureTransport":"false"}}}
+                ]"#,
+            ),
+            account: Some("111122223333".to_string()),
+        };
+
+        let request = request("s3:GetObject", Some("arn:aws:s3:::bucket/key"));
+        let evaluation = evaluate_entity(&policies, &[], Some(&bucket_policy), &request);
+        assert_eq!(evaluation.decision, Decision::Conditional);
+        assert_eq!(
+            evaluation.blocking_layer().map(|l| l.layer),
+            Some(PolicyLayer::ResourcePolicy)
+        );
+
+        let mut secure = request.clone();
+        secure.context.insert("aws:SecureTransport", "true".to_string());
+        let evaluation = evaluate_entity(&policies, &[], Some(&bucket_policy), &secure);
+        assert_eq!(evaluation.decision, Decision::Allowed);
+    }
+
+    #[test]
+    fn policy_variables_are_literal_before_version_2012_10_17() {
+        let statements = r#"[{"Effect":"Allow","Action":"s3:GetObject","Resource":"arn:aws:s3:::home/${aws:username}/*"}]"#;
+        let mut request = request("s3:GetObject", Some("arn:aws:s3:::home/alice/notes.txt"));
+        request.context.insert("aws:username", "alice".to_string());
+
+        let current = policy(statements);
+        let evaluation = evaluate(&[("home".to_string(), current)], &request);
+        assert_eq!(evaluation.decision, Decision::Allowed);
+
+        let legacy: PolicyDocument = format!(r#"{{"Version":"2008-10-17","Statement":{}}}"#, statements)
+            .parse()
+            .expect("test policy should parse");
+        let evaluation = evaluate(&[("home".to_string(), legacy)], &request);
+        assert_eq!(evaluation.decision, Decision::ImplicitDeny);
+    }
+}
@@ -0,0 +1,156 @@
+/// An IAM wildcard pattern, compiled once and matched against the whole text.
+/// `*` matches any run of characters, `?` matches exactly one, and every
+/// other character is literal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob {
+    tokens: Vec<Token>,
+    ignore_case: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    AnyChar,
+    AnyRun,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Self {
+        Self::compile(pattern.chars().map(|c| (c, false)), false)
+    }
+
+    /// Action names are matched without regard to case.
+    pub fn ignore_case(pattern: &str) -> Self {
+        Self::compile(pattern.chars().map(|c| (c, false)), true)
+    }
+
+    /// A pattern with its policy variables resolved, where characters
+    /// flagged as literal never act as wildcards.
+    pub fn resolved(pattern: &[(char, bool)]) -> Self {
+        Self::compile(pattern.iter().copied(), false)
+    }
+
+    fn compile(pattern: impl Iterator<Item = (char, bool)>, ignore_case: bool) -> Self {
+        let mut tokens: Vec<Token> = vec![];
+        for (c, literal) in pattern {
+            let token = match c {
+                '*' if !literal => Token::AnyRun,
+                '?' if !literal => Token::AnyChar,
+                c if ignore_case => Token::Literal(c.to_ascii_lowercase()),
+                c => Token::Literal(c),
+            };
+            // consecutive `*` behave like a single one
+            if token == Token::AnyRun && tokens.last() == Some(&Token::AnyRun) {
+                continue;
+            }
+            tokens.push(token);
+        }
+        Glob {
+            tokens,
+            ignore_case,
+        }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = if self.ignore_case {
+            text.chars().map(|c| c.to_ascii_lowercase()).collect()
+        } else {
+            text.chars().collect()
+        };
+        let tokens = &self.tokens;
+
+        let (mut p, mut t) = (0, 0);
+        let mut last_run: Option<usize> = None;
+        let mut run_text = 0;
+
+        while t < text.len() {
+            match tokens.get(p) {
+                Some(Token::AnyChar) => {
+                    p += 1;
+                    t += 1;
+                }
+                Some(Token::Literal(c)) if *c == text[t] => {
+                    p += 1;
+                    t += 1;
+                }
+                Some(Token::AnyRun) => {
+                    last_run = Some(p);
+                    run_text = t;
+                    p += 1;
+                }
+                // backtrack: let the last `*` swallow one more character
+                _ => match last_run {
+                    Some(run) => {
+                        p = run + 1;
+                        run_text += 1;
+                        t = run_text;
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        tokens[p..].iter().all(|token| *token == Token::AnyRun)
+    }
+}
+
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    Glob::new(pattern).is_match(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_whole_text() {
+        let glob = Glob::ignore_case("s3:Get*");
+        assert!(glob.is_match("s3:GetObject"));
+        assert!(glob.is_match("s3:Get"));
+        assert!(!glob.is_match("xs3:GetObjectFoo"));
+        assert!(!glob_matches("s3:GetObject", "s3:GetObjectAcl"));
+    }
+
+    #[test]
+    fn question_mark_matches_exactly_one_character() {
+        assert!(glob_matches("ec2:?escribe*", "ec2:DescribeInstances"));
+        assert!(glob_matches("a?c", "abc"));
+        assert!(!glob_matches("a?c", "ac"));
+        assert!(!glob_matches("a?c", "abbc"));
+    }
+
+    #[test]
+    fn regex_metacharacters_are_literal() {
+        assert!(glob_matches("arn:aws:s3:::my.bucket/(1)+[a]$", "arn:aws:s3:::my.bucket/(1)+[a]$"));
+        assert!(!glob_matches("my.bucket", "myxbucket"));
+        assert!(!glob_matches("a+", "aa"));
+        assert!(glob_matches("^key|*", "^key|value"));
+    }
+
+    #[test]
+    fn actions_ignore_case_and_resources_do_not() {
+        assert!(Glob::ignore_case("S3:getobject").is_match("s3:GetObject"));
+        assert!(Glob::ignore_case("iam:*User").is_match("IAM:CREATEUSER"));
+        assert!(!Glob::new("arn:aws:s3:::Bucket/*").is_match("arn:aws:s3:::bucket/key"));
+        assert!(Glob::new("arn:aws:s3:::Bucket/*").is_match("arn:aws:s3:::Bucket/key"));
+    }
+
+    #[test]
+    fn backtracks_over_several_wildcards() {
+        assert!(glob_matches("a*b*c", "abc"));
+        assert!(glob_matches("a*b*c", "axxbyybzzc"));
+        assert!(glob_matches("a*b*c", "abcbc"));
+        assert!(!glob_matches("a*b*c", "axxbyy"));
+        assert!(!glob_matches("a*b*c", "acb"));
+        assert!(glob_matches("**", ""));
+        assert!(!glob_matches("a*", ""));
+    }
+
+    #[test]
+    fn resolved_literals_are_not_wildcards() {
+        let pattern: Vec<(char, bool)> = "home/*/".chars().map(|c| (c, false)).chain([('*', true)]).collect();
+        let glob = Glob::resolved(&pattern);
+        assert!(glob.is_match("home/alice/*"));
+        assert!(!glob.is_match("home/alice/notes.txt"));
+    }
+}
@@ -1,40 +1,63 @@
-use aws_sdk_iam::error::SdkError;
+pub mod arn;
+pub mod auth_details;
+pub mod catalog;
+pub mod condition;
+pub mod diff;
+pub mod effective;
+pub mod error;
+pub mod evaluation;
+pub mod glob;
+pub mod policy;
+pub mod report;
+pub mod resource_policy;
+pub mod scp;
+pub mod session;
+pub mod source;
+pub mod trust;
+pub mod variables;
+pub mod verify;
+#[cfg(test)]
+mod test_util;
+
+use aws_sdk_iam::error::{ProvideErrorMetadata, SdkError};
 use aws_sdk_iam::operation::{
-    get_policy::*, get_policy_version::*, get_role::*, list_attached_role_policies::*,
-    get_user_policy::*, get_role_policy::*, get_group_policy::*, list_attached_group_policies::*,
-    list_attached_user_policies::*, list_group_policies::*, list_groups::*,
-    list_groups_for_user::*, list_policies::*, list_role_policies::*, list_roles::*,
-    list_user_policies::*, list_users::*,
+    get_group::GetGroupOutput, get_role::GetRoleOutput, get_user::GetUserOutput,
 };
 use aws_sdk_iam::types::{
-    AccessKey, AttachedPolicy, Group, Policy, PolicyScopeType, PolicyVersion, Role, User,
+    AttachedPolicy, ContextEntry, EvaluationResult, Group, Policy, PolicyScopeType, PolicyVersion,
+    Role, User,
 };
 use aws_sdk_iam::Client as iamClient;
+use error::Error;
+use source::{Entity, EntityKind};
 
 pub async fn get_policy(
     client: &iamClient,
     policy: AttachedPolicy,
-) -> Result<Policy, SdkError<GetPolicyError>> {
+) -> Result<Policy, Error> {
     let response = client
         .get_policy()
-        .set_policy_arn(policy.policy_arn)
+        .set_policy_arn(policy.policy_arn.clone())
         .send()
-        .await?;
-    let response = response.policy.unwrap();
-    Ok(response)
+        .await
+        .map_err(|e| policy_error("iam:GetPolicy", policy.policy_arn.clone(), e))?;
+    response
+        .policy
+        .ok_or_else(|| Error::PolicyNotFound(policy.policy_arn.unwrap_or_default()))
 }
 
 pub async fn get_user_policy(
     client: &iamClient,
     user_name: &String,
     policy_name: &String,
-) -> Result<String, SdkError<GetUserPolicyError>> {
+) -> Result<String, Error> {
     let response = client
         .get_user_policy()
         .user_name(user_name)
         .policy_name(policy_name)
         .send()
-        .await?;
+        .await
+        .map_err(|e| policy_error("iam:GetUserPolicy", Some(policy_name.clone()), e))?;
     let document = response.policy_document;
     Ok(document)
 }
@@ -43,13 +66,14 @@ pub async fn get_role_policy(
     client: &iamClient,
     role_name: &String,
     policy_name: &String,
-) -> Result<String, SdkError<GetRolePolicyError>> {
+) -> Result<String, Error> {
     let response = client
         .get_role_policy()
         .role_name(role_name)
         .policy_name(policy_name)
         .send()
-        .await?;
+        .await
+        .map_err(|e| policy_error("iam:GetRolePolicy", Some(policy_name.clone()), e))?;
     let document = response.policy_document;
     Ok(document)
 }
@@ -58,13 +82,14 @@ pub async fn get_group_policy(
     client: &iamClient,
     group_name: &String,
     policy_name: &String,
-) -> Result<String, SdkError<GetGroupPolicyError>> {
+) -> Result<String, Error> {
     let response = client
         .get_group_policy()
         .group_name(group_name)
         .policy_name(policy_name)
         .send()
-        .await?;
+        .await
+        .map_err(|e| policy_error("iam:GetGroupPolicy", Some(policy_name.clone()), e))?;
     let document = response.policy_document;
     Ok(document)
 }
@@ -73,63 +98,95 @@ pub async fn get_group_policy(
 pub async fn get_policy_version(
     client: &iamClient,
     policy: Policy,
-) -> Result<PolicyVersion, SdkError<GetPolicyVersionError>> {
+) -> Result<PolicyVersion, Error> {
     let response = client
         .get_policy_version()
-        .set_policy_arn(policy.arn)
+        .set_policy_arn(policy.arn.clone())
         .set_version_id(policy.default_version_id)
         .send()
-        .await?;
-    let response = response.policy_version.unwrap();
-    Ok(response)
+        .await
+        .map_err(|e| policy_error("iam:GetPolicyVersion", policy.arn.clone(), e))?;
+    response
+        .policy_version
+        .ok_or_else(|| Error::PolicyNotFound(policy.arn.unwrap_or_default()))
 }
 
 pub async fn list_roles(
     client: &iamClient,
     path_prefix: Option<String>,
-    marker: Option<String>,
-    max_items: Option<i32>,
-) -> Result<ListRolesOutput, SdkError<ListRolesError>> {
-    let response = client
+) -> Result<Vec<Role>, Error> {
+    let roles = client
         .list_roles()
         .set_path_prefix(path_prefix)
-        .set_marker(marker)
-        .set_max_items(max_items)
+        .into_paginator()
+        .items()
         .send()
-        .await?;
-    Ok(response)
+        .try_collect()
+        .await
+        .map_err(|e| Error::from_sdk("iam:ListRoles", None, e))?;
+    Ok(roles)
 }
 
 pub async fn get_role(
     client: &iamClient,
     role_name: String,
-) -> Result<GetRoleOutput, SdkError<GetRoleError>> {
-    let response = client.get_role().role_name(role_name).send().await?;
+) -> Result<GetRoleOutput, Error> {
+    let response = client
+        .get_role()
+        .role_name(&role_name)
+        .send()
+        .await
+        .map_err(|e| Error::from_sdk("iam:GetRole", Some(Entity::new(EntityKind::Role, role_name)), e))?;
     Ok(response)
 }
 
 
+pub async fn get_user(
+    client: &iamClient,
+    user_name: String,
+) -> Result<GetUserOutput, Error> {
+    let response = client
+        .get_user()
+        .user_name(&user_name)
+        .send()
+        .await
+        .map_err(|e| Error::from_sdk("iam:GetUser", Some(Entity::new(EntityKind::User, user_name)), e))?;
+    Ok(response)
+}
+
+pub async fn get_group(
+    client: &iamClient,
+    group_name: String,
+) -> Result<GetGroupOutput, Error> {
+    let response = client
+        .get_group()
+        .group_name(&group_name)
+        .send()
+        .await
+        .map_err(|e| Error::from_sdk("iam:GetGroup", Some(Entity::new(EntityKind::Group, group_name)), e))?;
+    Ok(response)
+}
 
 pub async fn list_users(
     client: &iamClient,
     path_prefix: Option<String>,
-    marker: Option<String>,
-    max_items: Option<i32>,
-) -> Result<ListUsersOutput, SdkError<ListUsersError>> {
-    let response = client
+) -> Result<Vec<User>, Error> {
+    let users = client
         .list_users()
         .set_path_prefix(path_prefix)
-        .set_marker(marker)
-        .set_max_items(max_items)
+        .into_paginator()
+        .items()
         .send()
-        .await?;
-    Ok(response)
+        .try_collect()
+        .await
+        .map_err(|e| Error::from_sdk("iam:ListUsers", None, e))?;
+    Ok(users)
 }
 
 pub async fn list_policies(
     client: iamClient,
     path_prefix: String,
-) -> Result<Vec<String>, SdkError<ListPoliciesError>> {
+) -> Result<Vec<String>, Error> {
     let list_policies = client
         .list_policies()
         .path_prefix(path_prefix)
@@ -138,15 +195,14 @@ pub async fn list_policies(
         .items()
         .send()
         .try_collect()
-        .await?;
+        .await
+        .map_err(|e| Error::from_sdk("iam:ListPolicies", None, e))?;
 
     let policy_names = list_policies
         .into_iter()
         .map(|p| {
-            let name = p
-                .policy_name
-                .unwrap_or_else(|| "Missing Policy Name".to_string());
-            name
+            p.policy_name
+                .unwrap_or_else(|| "Missing Policy Name".to_string())
         })
         .collect();
 
@@ -156,115 +212,170 @@ pub async fn list_policies(
 pub async fn list_groups(
     client: &iamClient,
     path_prefix: Option<String>,
-    marker: Option<String>,
-    max_items: Option<i32>,
-) -> Result<ListGroupsOutput, SdkError<ListGroupsError>> {
-    let response = client
+) -> Result<Vec<Group>, Error> {
+    let groups = client
         .list_groups()
         .set_path_prefix(path_prefix)
-        .set_marker(marker)
-        .set_max_items(max_items)
+        .into_paginator()
+        .items()
         .send()
-        .await?;
-
-    Ok(response)
+        .try_collect()
+        .await
+        .map_err(|e| Error::from_sdk("iam:ListGroups", None, e))?;
+    Ok(groups)
 }
 
 pub async fn list_attached_role_policies(
     client: &iamClient,
     role_name: &String,
-) -> Result<Option<Vec<AttachedPolicy>>, SdkError<ListAttachedRolePoliciesError>> {
-    let response = client
+) -> Result<Vec<AttachedPolicy>, Error> {
+    let attached_policies = client
         .list_attached_role_policies()
         .role_name(role_name)
+        .into_paginator()
+        .items()
         .send()
-        .await?;
-
-    let attached_policies = response.attached_policies;
+        .try_collect()
+        .await
+        .map_err(|e| Error::from_sdk("iam:ListAttachedRolePolicies", Some(Entity::new(EntityKind::Role, role_name)), e))?;
     Ok(attached_policies)
 }
 
 pub async fn list_attached_user_policies(
     client: &iamClient,
     user_name: &String,
-) -> Result<Option<Vec<AttachedPolicy>>, SdkError<ListAttachedUserPoliciesError>> {
-    let response = client
+) -> Result<Vec<AttachedPolicy>, Error> {
+    let attached_policies = client
         .list_attached_user_policies()
         .user_name(user_name)
+        .into_paginator()
+        .items()
         .send()
-        .await?;
-    let attached_policies = response.attached_policies;
+        .try_collect()
+        .await
+        .map_err(|e| Error::from_sdk("iam:ListAttachedUserPolicies", Some(Entity::new(EntityKind::User, user_name)), e))?;
     Ok(attached_policies)
 }
 
 pub async fn list_attached_group_policies(
     client: &iamClient,
     group_name: &String,
-) -> Result<Option<Vec<AttachedPolicy>>, SdkError<ListAttachedGroupPoliciesError>> {
-    let response = client
+) -> Result<Vec<AttachedPolicy>, Error> {
+    let attached_policies = client
         .list_attached_group_policies()
         .group_name(group_name)
+        .into_paginator()
+        .items()
         .send()
-        .await?;
-
-    let attached_policies = response.attached_policies;
+        .try_collect()
+        .await
+        .map_err(|e| Error::from_sdk("iam:ListAttachedGroupPolicies", Some(Entity::new(EntityKind::Group, group_name)), e))?;
     Ok(attached_policies)
 }
 
 pub async fn list_role_policies(
     client: &iamClient,
     role_name: &str,
-) -> Result<Vec<String>, SdkError<ListRolePoliciesError>> {
-    let response = client
+) -> Result<Vec<String>, Error> {
+    let policy_names = client
         .list_role_policies()
         .role_name(role_name)
+        .into_paginator()
+        .items()
         .send()
-        .await?;
-    let policy_names = response.policy_names;
+        .try_collect()
+        .await
+        .map_err(|e| Error::from_sdk("iam:ListRolePolicies", Some(Entity::new(EntityKind::Role, role_name)), e))?;
     Ok(policy_names)
 }
 
 pub async fn list_user_policies(
     client: &iamClient,
     user_name: &str,
-) -> Result<Vec<String>, SdkError<ListUserPoliciesError>> {
-    let response = client
+) -> Result<Vec<String>, Error> {
+    let policy_names = client
         .list_user_policies()
         .user_name(user_name)
+        .into_paginator()
+        .items()
         .send()
-        .await?;
-
-    let policy_names = response.policy_names;
+        .try_collect()
+        .await
+        .map_err(|e| Error::from_sdk("iam:ListUserPolicies", Some(Entity::new(EntityKind::User, user_name)), e))?;
     Ok(policy_names)
 }
+
 pub async fn list_group_policies(
     client: &iamClient,
     group_name: &str,
-) -> Result<Vec<String>, SdkError<ListGroupPoliciesError>> {
-    let response = client
+) -> Result<Vec<String>, Error> {
+    let policy_names = client
         .list_group_policies()
         .group_name(group_name)
+        .into_paginator()
+        .items()
         .send()
-        .await?;
-
-    let policy_names = response.policy_names;
+        .try_collect()
+        .await
+        .map_err(|e| Error::from_sdk("iam:ListGroupPolicies", Some(Entity::new(EntityKind::Group, group_name)), e))?;
     Ok(policy_names)
 }
 
 pub async fn list_groups_for_user(
     client: &iamClient,
     user_name: &str,
-) -> Result<Vec<String>, SdkError<ListGroupsForUserError>> {
-    let response = client
+) -> Result<Vec<String>, Error> {
+    let groups: Vec<Group> = client
         .list_groups_for_user()
         .user_name(user_name)
+        .into_paginator()
+        .items()
         .send()
-        .await?;
+        .try_collect()
+        .await
+        .map_err(|e| Error::from_sdk("iam:ListGroupsForUser", Some(Entity::new(EntityKind::User, user_name)), e))?;
 
-    let groups: Vec<String> = response
-        .groups
-        .iter()
-        .map(|g| g.group_name.clone())
-        .collect();
+    let groups: Vec<String> = groups.into_iter().map(|g| g.group_name).collect();
     Ok(groups)
 }
+
+/// Runs the IAM policy simulator for `policy_source_arn`, the ARN of a user,
+/// group or role, with one result per action and resource.
+pub async fn simulate_principal_policy(
+    client: &iamClient,
+    policy_source_arn: &str,
+    action_names: Vec<String>,
+    resource_arns: Option<Vec<String>>,
+    context_entries: Vec<ContextEntry>,
+    resource_policy: Option<String>,
+    resource_owner: Option<String>,
+) -> Result<Vec<EvaluationResult>, Error> {
+    let results = client
+        .simulate_principal_policy()
+        .policy_source_arn(policy_source_arn)
+        .set_action_names(Some(action_names))
+        .set_resource_arns(resource_arns)
+        .set_context_entries(Some(context_entries))
+        .set_resource_policy(resource_policy)
+        .set_resource_owner(resource_owner)
+        .into_paginator()
+        .items()
+        .send()
+        .try_collect()
+        .await
+        .map_err(|e| Error::from_sdk("iam:SimulatePrincipalPolicy", None, e))?;
+    Ok(results)
+}
+
+// A missing policy is reported by its ARN, or by name for an inline policy,
+// rather than as a missing entity, so the analysis can go on without it.
+fn policy_error<E>(operation: &'static str, policy: Option<String>, err: SdkError<E>) -> Error
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+{
+    let not_found = err.code() == Some("NoSuchEntity");
+    match policy {
+        Some(policy) if not_found => Error::PolicyNotFound(policy),
+        _ => Error::from_sdk(operation, None, err),
+    }
+}
@@ -1,22 +1,68 @@
-use aws_sdk_iam::operation::list_policies::ListPoliciesError;
-use aws_sdk_iam::types::AttachedPolicy;
-use aws_sdk_iam::error::SdkError;
 use clap::{Parser, ValueEnum};
-use futures::future::join_all;
-use regex::Regex;
-use serde_json::{to_string_pretty, Value};
-use std::iter::zip;
+use futures::stream::{self, StreamExt};
+use serde_json::to_string_pretty;
+use std::collections::HashSet;
+use std::path::PathBuf;
 use std::str::FromStr;
-use tokio;
-use urlencoding::decode;
 use colored::*;
 use inquire::{Select, Text};
 use std::fmt::Display;
-use aws_sdk_iam::Client as iamClient;
+use iam_service::auth_details::AuthorizationDetails;
+use iam_service::catalog::{ActionCatalog, ActionValidation};
+use iam_service::condition::RequestContext;
+use iam_service::diff::{diff_entities, ActionDiff};
+use iam_service::error::Error;
+use iam_service::effective::{list_allowed_actions, AllowedAction, Expansion, UNLISTED_ACTIONS};
+use iam_service::evaluation::{
+    evaluate_entity, Decision, Evaluation, LayeredEvaluation, MatchedStatement, PolicyLayer,
+    Request,
+};
+use iam_service::policy::{Effect, Statement};
+use iam_service::report::{
+    ActionMatrixReport, AllowedActionsReport, DecisionReport, DiffReport, EntityReport,
+    WhoCanReport, SCHEMA_VERSION,
+};
+use iam_service::resource_policy::{
+    account_of, fetch_resource_policy, load_resource_policy, ResourcePolicy,
+};
+use iam_service::scp::{load_scps, ScpLevel};
+use iam_service::session::collect_session_policies;
+use iam_service::trust::{analyze_trust_policy, TrustGrant};
+use iam_service::source::{
+    collect_entity_policies, Entity, EntityKind, EntityPolicies, InMemoryPolicySource,
+    PolicySource, PolicyWarning, SdkPolicySource,
+};
+use iam_service::verify::{verify_evaluations, PolicySimulator, SdkPolicySimulator, Verification};
 
 const ENTITY_TYPE: &str = "The type of IAM Entity";
 const ENTITY_NAME: &str = "The name of IAM Entity";
 const ACTION_NAME: &str = "The name of action IAM entity performed";
+const ACTION_NAMES: &str = "The name of action IAM entity performed. Repeat it or separate actions with commas to check several";
+const ACTIONS_FILE: &str = "A file listing actions to check, one per line";
+const RESOURCE_ARN: &str = "The ARN of the resource the action is performed on";
+const CONTEXT: &str = "A condition key in the request context, as key=value. Repeat for more keys";
+const SCP: &str = "An SCP JSON file, or a directory exported from the OU hierarchy, to evaluate as guardrails. Repeat for more";
+const AUTH_DETAILS: &str = "Output of `aws iam get-account-authorization-details` to analyze offline instead of calling IAM";
+const CONTEXT_FILE: &str = "A JSON file mapping condition keys to values for the request context";
+const RESOURCE_POLICY: &str = "A resource policy JSON file (bucket policy, key policy, ...) to evaluate together with identity policies";
+const RESOURCE_ACCOUNT: &str = "The account that owns the resource, when --resource-arn does not include it (S3)";
+const FETCH_RESOURCE_POLICY: &str = "Fetch the resource policy of --resource-arn from its service";
+const OUTPUT: &str = "Output format. json prints a versioned report for scripts";
+const SESSION_POLICY: &str = "A session policy JSON file passed to AssumeRole, to check what the session is allowed. Repeat for more";
+const SESSION_POLICY_ARN: &str = "The ARN of a managed session policy passed to AssumeRole. Repeat for more";
+const VERIFY: &str = "Cross-check the decision with the IAM policy simulator (iam:SimulatePrincipalPolicy)";
+const WHO_CAN: &str = "List every user and role in the account that is allowed an action";
+const DIFF: &str = "Compare the decisions two entities get, and show the statements behind each difference";
+const DIFF_LEFT: &str = "The first entity, as type/name, e.g. role/deployer";
+const DIFF_RIGHT: &str = "The second entity, as type/name";
+const DIFF_ACTION_NAMES: &str = "Actions to compare. Repeat it or separate actions with commas. By default every action either entity is allowed is compared";
+const LIST_ALLOWED: &str = "List every action an entity is allowed, grouped by service";
+// Exit codes. A check exits with its decision, anything that stops it with EXIT_ERROR.
+const EXIT_ALLOWED: i32 = 0;
+const EXIT_ERROR: i32 = 1;
+const EXIT_DENIED: i32 = 2;
+const EXIT_CONDITIONAL: i32 = 3;
+
 const ABOUT: &str = r#"CLI tool to check allowed actions for IAM entities.
 Use it inside an environment where the cli can retrieve IAM credentials, 
 which has IAMReadOnly or above permissions."#;
@@ -24,18 +70,105 @@ which has IAMReadOnly or above permissions."#;
 #[derive(Debug, clap::Parser)]
 #[command(about=ABOUT)]
 struct WhichAllowedArgs {
+    #[command(subcommand)]
+    pub command: Option<Command>,
     #[arg(long, help=ENTITY_TYPE)]
     pub entity_type: Option<EntityType>,
     #[arg(long, help=ENTITY_NAME)]
     pub entity_name: Option<String>,
+    #[arg(long, help=ACTION_NAMES, value_delimiter = ',')]
+    pub action_name: Vec<String>,
+    #[arg(long, help=ACTIONS_FILE)]
+    pub actions_file: Option<PathBuf>,
+    #[arg(long, help=VERIFY, conflicts_with = "auth_details")]
+    pub verify: bool,
+    #[command(flatten)]
+    pub session: SessionArgs,
+    #[command(flatten)]
+    pub shared: SharedArgs,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    #[command(about=WHO_CAN)]
+    WhoCan(WhoCanArgs),
+    #[command(about=LIST_ALLOWED)]
+    ListAllowed(ListAllowedArgs),
+    #[command(about=DIFF)]
+    Diff(DiffArgs),
+}
+
+#[derive(Debug, clap::Args)]
+struct WhoCanArgs {
     #[arg(long, help=ACTION_NAME)]
-    pub action_name: Option<String>,
+    pub action_name: String,
+}
+
+#[derive(Debug, clap::Args)]
+struct ListAllowedArgs {
+    #[arg(long, help=ENTITY_TYPE)]
+    pub entity_type: Option<EntityType>,
+    #[arg(long, help=ENTITY_NAME)]
+    pub entity_name: Option<String>,
+    #[command(flatten)]
+    pub session: SessionArgs,
+}
+
+// The policies of an assumed-role session, intersected with the role's own.
+#[derive(Debug, clap::Args)]
+struct SessionArgs {
+    #[arg(long, help=SESSION_POLICY)]
+    pub session_policy: Vec<PathBuf>,
+    #[arg(long, help=SESSION_POLICY_ARN)]
+    pub session_policy_arn: Vec<String>,
+}
+
+#[derive(Debug, clap::Args)]
+struct DiffArgs {
+    #[arg(long, help=DIFF_LEFT, value_parser = parse_entity)]
+    pub left: Entity,
+    #[arg(long, help=DIFF_RIGHT, value_parser = parse_entity)]
+    pub right: Entity,
+    #[arg(long, help=DIFF_ACTION_NAMES, value_delimiter = ',')]
+    pub action_name: Vec<String>,
+    #[arg(long, help=ACTIONS_FILE)]
+    pub actions_file: Option<PathBuf>,
+}
+
+// Options that apply to every mode, accepted before or after the subcommand.
+#[derive(Debug, clap::Args)]
+struct SharedArgs {
+    #[arg(long, global = true, help=RESOURCE_ARN)]
+    pub resource_arn: Option<String>,
+    #[arg(long, global = true, help=CONTEXT)]
+    pub context: Vec<String>,
+    #[arg(long, global = true, help=CONTEXT_FILE)]
+    pub context_file: Option<PathBuf>,
+    #[arg(long, global = true, help=SCP)]
+    pub scp: Vec<PathBuf>,
+    #[arg(long, global = true, help=AUTH_DETAILS)]
+    pub auth_details: Option<PathBuf>,
+    #[arg(long, global = true, help=RESOURCE_POLICY, conflicts_with = "fetch_resource_policy")]
+    pub resource_policy: Option<PathBuf>,
+    #[arg(long, global = true, help=RESOURCE_ACCOUNT)]
+    pub resource_account: Option<String>,
+    #[arg(long, global = true, help=FETCH_RESOURCE_POLICY, requires = "resource_arn")]
+    pub fetch_resource_policy: bool,
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text, help=OUTPUT)]
+    pub output: OutputFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
 }
 
 #[derive(Debug, Clone, ValueEnum)]
 enum EntityType {
     User,
     Role,
+    Group,
 }
 
 impl FromStr for EntityType {
@@ -45,288 +178,668 @@ impl FromStr for EntityType {
         match s {
             "user" => Ok(EntityType::User),
             "role" => Ok(EntityType::Role),
+            "group" => Ok(EntityType::Group),
             _ => Err(format!(
-                "'{}' is not a valid entity type. Use 'user' or 'role'.",
+                "'{}' is not a valid entity type. Use 'user', 'role' or 'group'.",
                 s
             )),
         }
     }
 }
 
+// `role/deployer`, `user/alice` or `group/admins`.
+fn parse_entity(s: &str) -> Result<Entity, String> {
+    let (entity_type, name) = s
+        .split_once('/')
+        .ok_or_else(|| format!("'{}' is not of the form type/name, e.g. role/deployer", s))?;
+    let entity_type: EntityType = entity_type.parse()?;
+    Ok(Entity::new(EntityKind::from(&entity_type), name))
+}
+
+impl From<&EntityType> for EntityKind {
+    fn from(entity_type: &EntityType) -> Self {
+        match entity_type {
+            EntityType::User => EntityKind::User,
+            EntityType::Role => EntityKind::Role,
+            EntityType::Group => EntityKind::Group,
+        }
+    }
+}
+
 impl Display for EntityType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
             EntityType::User=> write!(f, "user"),
             EntityType::Role=> write!(f, "role"),
+            EntityType::Group=> write!(f, "group"),
         }
     }
 }
 
 
 #[tokio::main]
-async fn main() -> Result<(), SdkError<ListPoliciesError>> {
-    let sdk_config = aws_config::load_from_env().await;
-    let client = aws_sdk_iam::Client::new(&sdk_config);
-    let args = WhichAllowedArgs::parse();
+async fn main() {
+    let args = parse_args();
 
-    let entity_type = match args.entity_type {
-        Some(e_t) => e_t,
-        None => Select::new(
-            "Select the type of IAM Entity:",
-            vec![EntityType::User, EntityType::Role],
-        )
-        .prompt()
-        .unwrap(),
-    };
-
-    
-    let entity_name = if let Some(e_n) = args.entity_name {
-        e_n
+    let sdk_config = if args.shared.auth_details.is_none() || args.shared.fetch_resource_policy {
+        Some(aws_config::load_from_env().await)
     } else {
-        set_entity_name(&client, &entity_type).await
+        None
     };
+    let resource_policy = load_resource_policy_arg(&args.shared, sdk_config.as_ref()).await;
 
-    let action_name = match args.action_name {
-        Some(a_n) => a_n,
-        None => Text::new("Enter the name of IAM action:")
-            .prompt()
-            .unwrap(),
-    };
+    match (&args.shared.auth_details, sdk_config) {
+        (Some(path), _) => {
+            let details = load_auth_details(path);
+            let source = InMemoryPolicySource::from(&details);
+            run(&source, None::<&SdkPolicySimulator>, args, resource_policy).await
+        }
+        (None, Some(sdk_config)) => {
+            let client = aws_sdk_iam::Client::new(&sdk_config);
+            let simulator = args.verify.then(|| SdkPolicySimulator::new(client.clone()));
+            run(&SdkPolicySource::new(client), simulator.as_ref(), args, resource_policy).await
+        }
+        (None, None) => unreachable!("the SDK config is loaded when not running offline"),
+    }
+}
 
+async fn run<S: PolicySource, V: PolicySimulator>(
+    source: &S,
+    simulator: Option<&V>,
+    args: WhichAllowedArgs,
+    resource_policy: Option<ResourcePolicy>,
+) {
+    let context = load_request_context(&args.shared.context, args.shared.context_file.as_ref());
+    let scp_levels = load_scps(&args.shared.scp).unwrap_or_else(|e| exit_with_error(e));
 
-    let attached_policy_pairs: Vec<(String, String)> = match entity_type {
-        EntityType::User => {
-            // user attached managed policy (both aws and customer managed)
-            let user_managed_p =
-                iam_service::list_attached_user_policies(&client, &entity_name)
-                    .await
-                    .unwrap()
-                    .unwrap();
-
-            let user_managed_p_document = user_managed_p.iter().map(|a_p| async {
-                let policy = iam_service::get_policy(&client, a_p.clone()).await.unwrap();
-                let policy_document = iam_service::get_policy_version(&client, policy)
-                    .await
-                    .unwrap()
-                    .document
-                    .unwrap();
-                policy_document
-            });
-            let mut user_managed_p_document: Vec<String> = join_all(user_managed_p_document).await;
-
-            let mut user_managed_p_name: Vec<String> = user_managed_p
-                .iter()
-                .map(|p| p.policy_name.clone().unwrap())
-                .collect();
-
-            // user attached inline policy
-            let mut user_inline_p_name = iam_service::list_user_policies(&client, &entity_name)
-                .await
-                .unwrap();
-
-            let user_inline_p_document = user_inline_p_name.iter().map(|p_n| async {
-                let policy_document = iam_service::get_user_policy(&client, &entity_name, p_n)
-                    .await
-                    .unwrap();
-                policy_document
-            });
-            let mut user_inline_p_document: Vec<String> = join_all(user_inline_p_document).await;
-
-            // group attached managed policies
-            let groups = iam_service::list_groups_for_user(&client, &entity_name)
-                .await
-                .unwrap();
-
-            let mut group_managed_p: Vec<AttachedPolicy> = Vec::new();
-            for group in groups.clone() {
-                let mut p = iam_service::list_attached_group_policies(&client, &group)
-                    .await
-                    .unwrap()
-                    .unwrap();
-                group_managed_p.append(&mut p);
-            }
+    if let Some(Command::WhoCan(who_can_args)) = args.command {
+        check_action_name(&who_can_args.action_name);
+        let request = Request {
+            action_name: who_can_args.action_name,
+            resource_arn: args.shared.resource_arn,
+            context,
+        };
+        who_can(source, &scp_levels, resource_policy.as_ref(), &request, args.shared.output).await;
+        return;
+    }
 
-            let group_managed_p_document = group_managed_p.iter().map(|a_p| async {
-                let policy = iam_service::get_policy(&client, a_p.clone()).await.unwrap();
-                let policy_document = iam_service::get_policy_version(&client, policy)
-                    .await
-                    .unwrap()
-                    .document
-                    .unwrap();
-                policy_document
-            });
-            let mut group_managed_p_document = join_all(group_managed_p_document).await;
-
-            let mut group_managed_p_name: Vec<String> = group_managed_p
-                .iter()
-                .map(|a_p| a_p.policy_name.clone().unwrap())
-                .collect();
-
-            // group attached inline policies
-            let mut group_inline_p_name: Vec<String> = Vec::new();
-            let mut group_inline_p_document: Vec<String> = Vec::new();
-            for group in groups {
-                let mut p_n = iam_service::list_group_policies(&client, &group)
-                    .await
-                    .unwrap();
-
-                let p_d = p_n.iter().map(|p_n| async {
-                    iam_service::get_group_policy(&client, &group, p_n)
-                        .await
-                        .unwrap()
-                });
-                let mut p_d = join_all(p_d).await;
-
-                group_inline_p_name.append(&mut p_n);
-                group_inline_p_document.append(&mut p_d);
+    if let Some(Command::ListAllowed(list_args)) = args.command {
+        let entity = select_entity(source, list_args.entity_type, list_args.entity_name).await;
+        let mut policies = collect_entity_policies(source, &entity)
+            .await
+            .unwrap_or_else(|e| exit_with_error(e));
+        add_session_policies(source, &entity, &list_args.session, &mut policies).await;
+        let request = Request {
+            action_name: String::new(),
+            resource_arn: args.shared.resource_arn,
+            context,
+        };
+        let allowed = list_allowed_actions(
+            &policies,
+            &scp_levels,
+            resource_policy.as_ref(),
+            &request,
+            ActionCatalog::bundled(),
+        );
+        match args.shared.output {
+            OutputFormat::Json => print_json(&AllowedActionsReport::new(
+                &entity,
+                &policies,
+                &request,
+                &allowed,
+                ActionCatalog::bundled(),
+            )),
+            OutputFormat::Text => {
+                print_allowed_actions(&entity, &allowed);
+                print_warnings(&entity, &policies.warnings);
             }
+        }
+        return;
+    }
 
-            let mut user_p_name: Vec<String> = Vec::new();
-            user_p_name.append(&mut user_managed_p_name);
-            user_p_name.append(&mut user_inline_p_name);
-            user_p_name.append(&mut group_managed_p_name);
-            user_p_name.append(&mut group_inline_p_name);
-
-            let mut user_p_document: Vec<String> = Vec::new();
-            user_p_document.append(&mut user_managed_p_document);
-            user_p_document.append(&mut user_inline_p_document);
-            user_p_document.append(&mut group_managed_p_document);
-            user_p_document.append(&mut group_inline_p_document);
-
-            assert_eq!(user_p_name.len(), user_p_document.len());
-            let user_result: Vec<(String, String)> = zip(user_p_name, user_p_document).collect();
-            user_result
-        }
-
-        EntityType::Role => {
-            let role_managed_p =
-                iam_service::list_attached_role_policies(&client, &entity_name)
-                    .await
-                    .unwrap()
-                    .unwrap();
-
-            let role_managed_p_document = role_managed_p.iter().map(|a_p| async {
-                let policy = iam_service::get_policy(&client, a_p.clone()).await.unwrap();
-                let policy_document = iam_service::get_policy_version(&client, policy)
-                    .await
-                    .unwrap()
-                    .document
-                    .unwrap();
-                policy_document
-            });
-            let mut role_managed_p_document: Vec<String> = join_all(role_managed_p_document).await;
-
-            let mut role_managed_p_name: Vec<String> = role_managed_p
-                .iter()
-                .map(|p| p.policy_name.clone().unwrap())
-                .collect();
-
-            // role attached inline policy
-            let mut role_inline_p_name = iam_service::list_role_policies(&client, &entity_name)
-                .await
-                .unwrap();
-
-            let role_inline_p_document = role_inline_p_name.iter().map(|p_n| async {
-                let policy_document = iam_service::get_role_policy(&client, &entity_name, p_n)
-                    .await
-                    .unwrap();
-                policy_document
-            });
-            let mut role_inline_p_document: Vec<String> = join_all(role_inline_p_document).await;
-
-            let mut role_p_name: Vec<String> = Vec::new();
-            role_p_name.append(&mut role_managed_p_name);
-            role_p_name.append(&mut role_inline_p_name);
-
-            let mut role_p_document: Vec<String> = Vec::new();
-            role_p_document.append(&mut role_managed_p_document);
-            role_p_document.append(&mut role_inline_p_document);
-
-            assert_eq!(role_p_name.len(), role_p_document.len());
-            let role_result: Vec<(String, String)> = zip(role_p_name, role_p_document).collect();
-            role_result
+    if let Some(Command::Diff(diff_args)) = args.command {
+        let action_names = collect_action_names(diff_args.action_name, diff_args.actions_file.as_ref());
+        for action_name in &action_names {
+            check_action_name(action_name);
         }
-    };
+        let (left, right) = futures::join!(
+            collect_entity_policies(source, &diff_args.left),
+            collect_entity_policies(source, &diff_args.right)
+        );
+        let (left, right) = left.and_then(|l| Ok((l, right?))).unwrap_or_else(|e| exit_with_error(e));
+        let request = Request {
+            action_name: String::new(),
+            resource_arn: args.shared.resource_arn,
+            context,
+        };
+        let diffs = diff_entities(
+            &left,
+            &right,
+            &scp_levels,
+            resource_policy.as_ref(),
+            &request,
+            &action_names,
+            ActionCatalog::bundled(),
+        );
+        match args.shared.output {
+            OutputFormat::Json => print_json(&DiffReport::new(
+                (&diff_args.left, &left),
+                (&diff_args.right, &right),
+                &request,
+                &diffs,
+            )),
+            OutputFormat::Text => {
+                print_diff(&diff_args.left, &diff_args.right, &diffs);
+                print_warnings(&diff_args.left, &left.warnings);
+                print_warnings(&diff_args.right, &right.warnings);
+            }
+        }
+        return;
+    }
 
+    let entity = select_entity(source, args.entity_type, args.entity_name).await;
 
-    //println!("{:?}", attached_policy_pairs);
+    let mut action_names = collect_action_names(args.action_name, args.actions_file.as_ref());
+    if action_names.is_empty() {
+        action_names.push(set_action_name());
+    }
+    for action_name in &action_names {
+        check_action_name(action_name);
+    }
 
-    //println!("{:?}", attached_policy_pairs.clone().len());
+    let mut policies = collect_entity_policies(source, &entity)
+        .await
+        .unwrap_or_else(|e| exit_with_error(e));
+    add_session_policies(source, &entity, &args.session, &mut policies).await;
 
-    let decoded_policy_pairs: Vec<(String, Value)> = attached_policy_pairs
+    let mut request = Request {
+        action_name: action_names[0].clone(),
+        resource_arn: args.shared.resource_arn,
+        context,
+    };
+    let evaluations: Vec<LayeredEvaluation> = action_names
         .iter()
-        .filter_map(|(policy_name, policy_document)| {
-            let decoded = decode(policy_document).ok()?;
-            let json: Value = serde_json::from_str(&decoded).ok()?;
-            Some((policy_name.clone(), json))
+        .map(|action_name| {
+            request.action_name = action_name.clone();
+            evaluate_entity(&policies, &scp_levels, resource_policy.as_ref(), &request)
         })
-        .collect(); 
+        .collect();
+    request.action_name = action_names[0].clone();
+    let mut verifications = match simulator {
+        Some(simulator) => {
+            let resource_policy = resource_policy.as_ref();
+            verify_actions(simulator, &policies, &action_names, &request, resource_policy, &evaluations).await
+        }
+        None => vec![None; action_names.len()],
+    };
+
+    if action_names.len() > 1 {
+        match args.shared.output {
+            OutputFormat::Json => {
+                let mut report = ActionMatrixReport::new(&entity, &policies, &request, &action_names, &evaluations);
+                for (result, verification) in report.results.iter_mut().zip(verifications) {
+                    result.verification = verification;
+                }
+                print_json(&report);
+            }
+            OutputFormat::Text => {
+                print_action_matrix(&entity, &action_names, &evaluations);
+                for (action_name, verification) in action_names.iter().zip(&verifications) {
+                    if let Some(verification) = verification {
+                        print_verification(action_name, verification);
+                    }
+                }
+                print_warnings(&entity, &policies.warnings);
+                if let Some(document) = &policies.trust_policy {
+                    print_trust_report(&entity, &analyze_trust_policy(document));
+                }
+            }
+        }
+        // A denied action outweighs one that is only conditionally allowed.
+        let decision = evaluations
+            .iter()
+            .map(|e| e.decision)
+            .max_by_key(|d| match d {
+                Decision::Allowed => 0,
+                Decision::Conditional => 1,
+                Decision::ExplicitDeny | Decision::ImplicitDeny => 2,
+            })
+            .unwrap_or(Decision::Allowed);
+        std::process::exit(exit_code(decision));
+    }
+    let action_name = request.action_name.clone();
+    let evaluation = &evaluations[0];
+    let verification = verifications.remove(0);
+    if args.shared.output == OutputFormat::Json {
+        let mut report = DecisionReport::new(&entity, &policies, &request, evaluation);
+        report.result.verification = verification;
+        print_json(&report);
+        std::process::exit(exit_code(evaluation.decision));
+    }
 
-    // println!("{:?}", attached_policy_pairs.clone().len());
+    let target = match &request.resource_arn {
+        Some(resource_arn) => format!("{} on {}", action_name, resource_arn),
+        None => action_name.clone(),
+    };
 
-    let policies_iter = decoded_policy_pairs.iter();
-    let mut matching_policy = vec![];
+    for layer in &evaluation.layers {
+        if layer.layer != PolicyLayer::Identity {
+            println!("{}", format!("[*] Checking the {}", layer.name).bold());
+        }
+        if !layer.required {
+            let message = format!("[*] Not required to allow this action within the account: {}", layer.name);
+            println!("{}", message.dimmed());
+        }
+        let layer_evaluation = &layer.evaluation;
+        print_matched_statements(layer_evaluation, &target);
 
-    for (attached_policy, policy_json) in policies_iter {
-        let statements = policy_json
-            .get("Statement")
-            .expect("Statment in a policy is expected")
-            .as_array()
-            .expect("Statment is always an array");
+        if layer.layer == PolicyLayer::Identity
+            && layer_evaluation.allowed.is_empty()
+            && layer_evaluation.denied.is_empty()
+            && layer_evaluation.conditional.is_empty()
+        {
+            let message: &str = "[*] No policies allowed this action";
+            println!("{}", message.bright_red().bold());
+        }
+    }
 
-        let mut allowed_statements = vec![];
+    print_verdict(evaluation);
+    if let Some(verification) = &verification {
+        print_verification(&action_name, verification);
+    }
+    print_warnings(&entity, &policies.warnings);
 
-        for statement in statements {
-            if check_action_in_statement(statement, &action_name) {
-                allowed_statements.push(statement);
-            }
+    if let Some(document) = &policies.trust_policy {
+        print_trust_report(&entity, &analyze_trust_policy(document));
+    }
+
+    std::process::exit(exit_code(evaluation.decision));
+}
+
+// Only sessions of roles, and of users through GetFederationToken, have session policies.
+async fn add_session_policies<S: PolicySource>(
+    source: &S,
+    entity: &Entity,
+    session: &SessionArgs,
+    policies: &mut EntityPolicies,
+) {
+    if session.session_policy.is_empty() && session.session_policy_arn.is_empty() {
+        return;
+    }
+    if entity.kind == EntityKind::Group {
+        exit_with_error("Session policies apply to role and federated user sessions, not to groups");
+    }
+    let session_policies = collect_session_policies(source, &session.session_policy, &session.session_policy_arn)
+        .await
+        .unwrap_or_else(|e| exit_with_error(e));
+    (policies.session_policies, policies.session_policy_origins) = session_policies.into_iter().unzip();
+}
+
+// Runs every action through the IAM policy simulator and compares the results
+// with `evaluations`. When the simulator cannot be run, the check goes on without it.
+async fn verify_actions<V: PolicySimulator>(
+    simulator: &V,
+    policies: &EntityPolicies,
+    action_names: &[String],
+    request: &Request,
+    resource_policy: Option<&ResourcePolicy>,
+    evaluations: &[LayeredEvaluation],
+) -> Vec<Option<Verification>> {
+    let unverified = vec![None; action_names.len()];
+    if !policies.session_policies.is_empty() {
+        let message = "[!] Session policies are not sent to the IAM policy simulator, so it may allow more";
+        eprintln!("{}", message.bright_yellow());
+    }
+    let Some(arn) = &policies.arn else {
+        eprintln!("{}", "[!] Could not verify: the ARN of the entity is unknown".bright_yellow());
+        return unverified;
+    };
+    let verifications =
+        verify_evaluations(simulator, arn, policies, action_names, request, resource_policy, evaluations).await;
+    verifications.unwrap_or_else(|e| {
+        let message = format!("[!] Could not run the IAM policy simulator: {}", e);
+        eprintln!("{}", message.bright_yellow());
+        unverified
+    })
+}
+
+fn print_verification(action_name: &str, verification: &Verification) {
+    if verification.agrees {
+        let message = format!(
+            "[*] The IAM policy simulator agrees on {}: {}",
+            action_name, verification.simulator.decision
+        );
+        println!("{}", message.bright_green());
+    } else {
+        let message = format!("[!] The IAM policy simulator disagrees on {}:", action_name);
+        println!("{}", message.bright_yellow().bold());
+        for disagreement in &verification.disagreements {
+            println!("{}", format!("  {}", disagreement).bright_yellow());
         }
+    }
+    if !verification.simulator.missing_context_keys.is_empty() {
+        println!(
+            "  The simulator was not given these context keys: {}",
+            verification.simulator.missing_context_keys.join(", ")
+        );
+    }
+}
 
-        if !allowed_statements.is_empty() {
-            matching_policy.push(attached_policy);
-            println!("[*] This policy : {}", attached_policy.bright_green().bold());
-            for statement in allowed_statements {
-                match to_string_pretty(statement) {
-                    Ok(pretty) => println!("Statement:\n{}\nAllowed {}\n", pretty.cyan(), action_name.clone()),
-                    Err(e) => eprintln!("Pretty print error: {}", e),
-                }
+// Listed after the results, so that a deny is never read without knowing
+// that some policies were missing from it.
+fn print_warnings(entity: &Entity, warnings: &[PolicyWarning]) {
+    if warnings.is_empty() {
+        return;
+    }
+    let message = format!(
+        "[!] Warnings: these could not be read for the {} and were left out of the analysis, so a deny may be wrong:",
+        entity
+    );
+    println!("{}", message.bright_yellow().bold());
+    for warning in warnings {
+        println!("{}", format!("  {}", warning).bright_yellow());
+    }
+}
+
+fn exit_code(decision: Decision) -> i32 {
+    match decision {
+        Decision::Allowed => EXIT_ALLOWED,
+        Decision::ExplicitDeny | Decision::ImplicitDeny => EXIT_DENIED,
+        Decision::Conditional => EXIT_CONDITIONAL,
+    }
+}
+
+// clap exits with 2 on a usage error, which would read as EXIT_DENIED.
+fn parse_args() -> WhichAllowedArgs {
+    WhichAllowedArgs::try_parse().unwrap_or_else(|e| {
+        let _ = e.print();
+        // --help and --version also end up here, on stdout.
+        std::process::exit(if e.use_stderr() { EXIT_ERROR } else { 0 })
+    })
+}
+
+fn exit_with_error(error: impl Display) -> ! {
+    eprintln!("{}", format!("Error: {}", error).bright_red());
+    std::process::exit(EXIT_ERROR);
+}
+
+fn print_json<T: serde::Serialize>(report: &T) {
+    match to_string_pretty(report) {
+        Ok(json) => println!("{}", json),
+        Err(e) => exit_with_error(format!("Could not serialize the report: {}", e)),
+    }
+}
+
+// Evaluates every user and role, and prints those the action is allowed for.
+async fn who_can<S: PolicySource>(
+    source: &S,
+    scp_levels: &[ScpLevel],
+    resource_policy: Option<&ResourcePolicy>,
+    request: &Request,
+    output: OutputFormat,
+) {
+    let mut entities = vec![];
+    for kind in [EntityKind::User, EntityKind::Role] {
+        let names = source.list_entity_names(kind).await.unwrap_or_else(|e| exit_with_error(e));
+        entities.extend(names.into_iter().map(|name| Entity::new(kind, name)));
+    }
+
+    let evaluations = stream::iter(&entities)
+        .map(|entity| async move {
+            let policies = collect_entity_policies(source, entity).await?;
+            let evaluation = evaluate_entity(&policies, scp_levels, resource_policy, request);
+            Ok::<_, Error>((policies, evaluation))
+        })
+        .buffered(8)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut rows: Vec<[String; 5]> = vec![];
+    let mut principals = vec![];
+    // Principals with policies that could not be read, and whether they were denied.
+    let mut incomplete = vec![];
+    for (entity, evaluation) in entities.iter().zip(evaluations) {
+        let (policies, evaluation) = match evaluation {
+            Ok(evaluation) => evaluation,
+            Err(e) => {
+                let message = format!("[!] Skipped {}: {}", entity, e);
+                eprintln!("{}", message.bright_yellow());
+                continue;
             }
+        };
+        let allowed = matches!(evaluation.decision, Decision::Allowed | Decision::Conditional);
+        if !policies.warnings.is_empty() {
+            incomplete.push((entity, policies.clone(), !allowed));
+        }
+        if !allowed {
+            continue;
         }
+        if output == OutputFormat::Json {
+            principals.push(DecisionReport::new(entity, &policies, request, &evaluation));
+            continue;
+        }
+        let (policy_name, statement) = match evaluation.deciding_statement() {
+            Some(deciding) => (deciding.policy_name.clone(), describe_statement(&deciding.statement)),
+            None => (String::new(), String::new()),
+        };
+        rows.push([
+            entity.kind.to_string(),
+            entity.name.clone(),
+            evaluation.decision.to_string(),
+            policy_name,
+            statement,
+        ]);
+    }
 
+    if output == OutputFormat::Json {
+        print_json(&WhoCanReport {
+            schema_version: SCHEMA_VERSION,
+            action: request.action_name.clone(),
+            resource: request.resource_arn.clone(),
+            principals,
+            incomplete_principals: incomplete
+                .iter()
+                .filter(|(_, _, denied)| *denied)
+                .map(|(entity, policies, _)| EntityReport::new(entity, policies))
+                .collect(),
+        });
+        return;
     }
 
-    if matching_policy.is_empty() {
-        let message: &str = "[*] No policies allowed this action";
+    if rows.is_empty() {
+        let message = format!("[*] No users or roles are allowed {}", request.action_name);
         println!("{}", message.bright_red().bold());
+    } else {
+        print_table(["TYPE", "PRINCIPAL", "DECISION", "POLICY", "STATEMENT"], &rows, 2);
     }
+    for (entity, policies, _) in &incomplete {
+        print_warnings(entity, &policies.warnings);
+    }
+}
 
-    Ok(())
+// One row per action, evaluated against the same fetched policies.
+fn print_action_matrix(entity: &Entity, action_names: &[String], evaluations: &[LayeredEvaluation]) {
+    println!("{}", format!("[*] Checking {} actions for the {}", action_names.len(), entity).bold());
+    let rows: Vec<[String; 4]> = action_names
+        .iter()
+        .zip(evaluations)
+        .map(|(action_name, evaluation)| {
+            let [policy_name, statement] = deciding_cells(evaluation);
+            [action_name.clone(), evaluation.decision.to_string(), policy_name, statement]
+        })
+        .collect();
+    print_table(["ACTION", "DECISION", "POLICY", "STATEMENT"], &rows, 1);
 }
 
-async fn set_entity_name(client: &iamClient, entity_type: &EntityType) -> String {
-    let entity_list: Vec<String> = match entity_type {
-        EntityType::Role => {
-            let vec_role = iam_service::list_roles(client, None, None, Some(1000))
-            .await
-            .unwrap()
-            .roles;
-
-            vec_role.iter()
-                .map(|r| r.role_name.clone())
-                .collect()
-        },
-        EntityType::User => {
-            let vec_user = iam_service::list_users(client, None, None, Some(1000))
-            .await
-            .unwrap()
-            .users;
+// Two rows per differing action, one for each entity.
+fn print_diff(left: &Entity, right: &Entity, diffs: &[ActionDiff]) {
+    if diffs.is_empty() {
+        let message = format!("[*] The {} and the {} get the same decisions", left, right);
+        println!("{}", message.bright_green().bold());
+        return;
+    }
+
+    let count = match diffs.len() {
+        1 => "1 action differs".to_string(),
+        n => format!("{} actions differ", n),
+    };
+    let message = format!("[*] {} between the {} and the {}", count, left, right);
+    println!("{}", message.bold());
+    let rows: Vec<[String; 5]> = diffs
+        .iter()
+        .flat_map(|diff| {
+            [(left, &diff.left), (right, &diff.right)].map(|(entity, evaluation)| {
+                let [policy_name, statement] = deciding_cells(evaluation);
+                [
+                    diff.action.clone(),
+                    entity.to_string(),
+                    evaluation.decision.to_string(),
+                    policy_name,
+                    statement,
+                ]
+            })
+        })
+        .collect();
+    print_table(["ACTION", "PRINCIPAL", "DECISION", "POLICY", "STATEMENT"], &rows, 2);
+}
+
+// The policy and statement behind a decision. Without a deciding statement,
+// the layer that failed to allow the action stands in for the policy.
+fn deciding_cells(evaluation: &LayeredEvaluation) -> [String; 2] {
+    match (evaluation.deciding_statement(), evaluation.blocking_layer()) {
+        (Some(deciding), _) => [deciding.policy_name.clone(), describe_statement(&deciding.statement)],
+        (None, Some(layer)) if layer.layer != PolicyLayer::Identity => [layer.name.clone(), String::new()],
+        _ => [String::new(), String::new()],
+    }
+}
+
+// Left-aligned columns, each row colored by the decision in `decision_column`.
+fn print_table<const N: usize>(header: [&str; N], rows: &[[String; N]], decision_column: usize) {
+    let header = header.map(String::from);
+    let mut widths = header.clone().map(|h| h.len());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
 
-            vec_user.iter()
-                .map(|u| u.user_name.clone())
-                .collect()
-        },
+    let format_row = |row: &[String; N]| {
+        row.iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
     };
+    println!("{}", format_row(&header).bold());
+    for row in rows {
+        let line = format_row(row);
+        let decision = &row[decision_column];
+        if *decision == Decision::Allowed.to_string() {
+            println!("{}", line.bright_green());
+        } else if *decision == Decision::Conditional.to_string() {
+            println!("{}", line.bright_yellow());
+        } else {
+            println!("{}", line.bright_red());
+        }
+    }
+}
+
+// The Sid when the statement has one, its compact JSON otherwise.
+fn describe_statement(statement: &Statement) -> String {
+    match &statement.sid {
+        Some(sid) => sid.clone(),
+        None => statement.raw().to_string(),
+    }
+}
 
+// Prompts for whichever of the entity type and name was not given.
+async fn select_entity<S: PolicySource>(
+    source: &S,
+    entity_type: Option<EntityType>,
+    entity_name: Option<String>,
+) -> Entity {
+    let entity_type = match entity_type {
+        Some(e_t) => e_t,
+        None => Select::new(
+            "Select the type of IAM Entity:",
+            vec![EntityType::User, EntityType::Role, EntityType::Group],
+        )
+        .prompt()
+        .unwrap_or_else(|e| exit_with_error(e)),
+    };
+
+    let entity_name = if let Some(e_n) = entity_name {
+        e_n
+    } else {
+        let entity_list = source
+            .list_entity_names(EntityKind::from(&entity_type))
+            .await
+            .unwrap_or_else(|e| exit_with_error(e));
+        set_entity_name(entity_list)
+    };
+
+    Entity::new(EntityKind::from(&entity_type), entity_name)
+}
+
+fn print_allowed_actions(entity: &Entity, allowed: &[AllowedAction]) {
+    if allowed.is_empty() {
+        let message = format!("[*] No actions are allowed for the {}", entity);
+        println!("{}", message.bright_red().bold());
+        return;
+    }
+
+    let catalog = ActionCatalog::bundled();
+    let width = allowed.iter().map(|a| a.action.len()).max().unwrap_or_default();
+    let mut services: Vec<(String, Vec<&AllowedAction>)> = vec![];
+    for action in allowed {
+        let prefix = action.action.split(':').next().unwrap_or_default().to_lowercase();
+        match services.iter_mut().find(|(p, _)| *p == prefix) {
+            Some((_, actions)) => actions.push(action),
+            None => services.push((prefix, vec![action])),
+        }
+    }
+
+    println!("{}", format!("[*] Actions allowed for the {}", entity).bold());
+    for (prefix, actions) in services {
+        let service_name = match catalog.service(&prefix) {
+            Some(service) => service.name.clone(),
+            None if prefix == UNLISTED_ACTIONS => "every service not in the bundled catalog".to_string(),
+            None => "not in the bundled catalog".to_string(),
+        };
+        let mut header = format!("[*] {} ({}): {} actions", prefix, service_name, actions.len());
+        if actions.iter().any(|a| a.expansion == Expansion::Truncated) {
+            header.push_str(", truncated: the catalog lists only part of the service, and wildcards may allow more");
+        }
+        println!("{}", header.bold());
+        for action in actions {
+            let access_level = catalog
+                .action(&action.action)
+                .map(|(_, a)| a.access_level.to_string())
+                .unwrap_or_default();
+            let mut line = format!(
+                "  {:<width$}  {:<22}  {}",
+                action.action,
+                access_level,
+                action.policy_name,
+                width = width
+            );
+            if action.expansion == Expansion::NotExpanded {
+                match action.excludes.as_slice() {
+                    [] => line.push_str("  (not expanded)"),
+                    excludes => line.push_str(&format!("  (not expanded, excludes {})", excludes.join(", "))),
+                }
+            }
+            match action.decision {
+                Decision::Conditional => println!("{}", format!("{}  (conditional)", line).bright_yellow()),
+                _ => println!("{}", line.bright_green()),
+            }
+        }
+    }
+}
+
+fn set_entity_name(entity_list: Vec<String>) -> String {
     let autocomplete_closure = move |input: &str| {
             Ok(
                 entity_list.iter()
@@ -339,54 +852,247 @@ async fn set_entity_name(client: &iamClient, entity_type: &EntityType) -> String
         .with_page_size(5)
         .with_autocomplete(autocomplete_closure)
         .prompt()
-        .unwrap();
+        .unwrap_or_else(|e| exit_with_error(e));
 
     result
 
 }
 
-fn check_action_in_statement(statement: &Value, action_name: &String) -> bool {
-    if statement
-        .get("Effect")
-        .expect("Effect is expected")
-        .as_str()
-        == Some("Allow")
-    {
-        let action = statement
-            .get("Action")
-            .expect("Action is expected in statement");
+fn set_action_name() -> String {
+    let autocomplete_closure = |input: &str| {
+        if input.is_empty() {
+            return Ok(vec![]);
+        }
+        Ok(ActionCatalog::bundled().complete(input, 20))
+    };
 
-        match action {
-            Value::String(s) => {
-                if s == action_name {
-                    return true;
-                }
-                let pattern = s.replace("*", ".*");
-                let re = Regex::new(&pattern).expect("Invalid regex pattern");
-                re.is_match(&action_name)
+    Text::new("Enter the name of IAM action:")
+        .with_page_size(5)
+        .with_autocomplete(autocomplete_closure)
+        .prompt()
+        .unwrap_or_else(|e| exit_with_error(e))
+}
+
+// Warns about action names the bundled catalog does not know. The catalog is a
+// snapshot, so an unknown action is still evaluated rather than rejected.
+fn check_action_name(action_name: &str) {
+    if action_name.contains(['*', '?']) {
+        return;
+    }
+    let catalog = ActionCatalog::bundled();
+    match catalog.validate(action_name) {
+        ActionValidation::Known => {}
+        ActionValidation::Malformed => {
+            exit_with_error(format!("'{}' is not an action name, expected service:Action", action_name))
+        }
+        ActionValidation::UnknownService => {
+            let prefix = action_name.split(':').next().unwrap_or_default();
+            let message = format!(
+                "[!] The service '{}' is not in the bundled catalog, so {} was not validated",
+                prefix, action_name
+            );
+            eprintln!("{}", message.bright_yellow());
+        }
+        ActionValidation::UnknownAction => {
+            let suggestions = catalog.suggest(action_name, 3);
+            let mut message = format!("[!] {} is not in the bundled catalog", action_name);
+            if !suggestions.is_empty() {
+                message.push_str(&format!(". Did you mean: {}?", suggestions.join(", ")));
             }
+            eprintln!("{}", message.bright_yellow());
+        }
+    }
+}
 
-            Value::Array(arr) => {
-                for action_elem in arr.clone() {
-                    let action_str = action_elem
-                        .as_str()
-                        .expect("Action Array must be an array of string");
-                    if action_str == action_name {
-                        return true;
-                    }
-                    let pattern = action_str.replace("*", ".*");
-                    let re = Regex::new(&pattern).expect("Invalid regex pattern");
-                    if re.is_match(&action_name) {
-                        return true;
-                    }
-                }
-                false
+fn print_matched_statements(evaluation: &Evaluation, target: &str) {
+    for (policy_name, statements) in group_by_policy(&evaluation.allowed) {
+        println!("[*] This policy : {}", policy_name.bright_green().bold());
+        for statement in statements {
+            match to_string_pretty(statement) {
+                Ok(pretty) => println!("Statement:\n{}\nAllowed {}\n", pretty.cyan(), target),
+                Err(e) => eprintln!("Pretty print error: {}", e),
             }
+        }
+    }
 
-            _ => false,
+    for (policy_name, statements) in group_by_policy(&evaluation.denied) {
+        println!("[*] This policy : {}", policy_name.bright_red().bold());
+        for statement in statements {
+            match to_string_pretty(statement) {
+                Ok(pretty) => println!("Statement:\n{}\nDenied {}\n", pretty.cyan(), target),
+                Err(e) => eprintln!("Pretty print error: {}", e),
+            }
         }
-    } else {
-        false
+    }
+
+    for (policy_name, statements) in group_by_policy(&evaluation.conditional) {
+        println!("[*] This policy : {}", policy_name.bright_yellow().bold());
+        for statement in statements {
+            let effect = match statement.effect {
+                Effect::Allow => "allows",
+                Effect::Deny => "denies",
+            };
+            match to_string_pretty(statement) {
+                Ok(pretty) => println!("Statement:\n{}\nConditionally {} {}\n", pretty.cyan(), effect, target),
+                Err(e) => eprintln!("Pretty print error: {}", e),
+            }
+        }
+    }
+}
+
+fn print_trust_report(entity: &Entity, grants: &[TrustGrant]) {
+    for effect in [Effect::Allow, Effect::Deny] {
+        let grants: Vec<&TrustGrant> = grants.iter().filter(|g| g.effect == effect).collect();
+        if grants.is_empty() {
+            continue;
+        }
+        let (heading, color) = match effect {
+            Effect::Allow => ("can be assumed by", Color::BrightGreen),
+            Effect::Deny => ("can not be assumed by", Color::BrightRed),
+        };
+        println!("{}", format!("[*] The {} {}:", entity, heading).bold());
+        for grant in grants {
+            for principal in &grant.principals {
+                println!("  {}", principal.to_string().color(color));
+            }
+            println!("    with {}", grant.actions.join(", "));
+            for condition in &grant.conditions {
+                println!("    when {}", condition.to_string().bright_yellow());
+            }
+        }
+    }
+}
+
+async fn load_resource_policy_arg(
+    args: &SharedArgs,
+    sdk_config: Option<&aws_config::SdkConfig>,
+) -> Option<ResourcePolicy> {
+    let account = args.resource_account.clone().or_else(|| {
+        args.resource_arn
+            .as_deref()
+            .and_then(account_of)
+            .map(|a| a.to_string())
+    });
+
+    if let Some(path) = &args.resource_policy {
+        let resource_policy = load_resource_policy(path, account).unwrap_or_else(|e| exit_with_error(e));
+        return Some(resource_policy);
+    }
+
+    let (true, Some(sdk_config), Some(resource_arn)) =
+        (args.fetch_resource_policy, sdk_config, &args.resource_arn)
+    else {
+        return None;
+    };
+    match fetch_resource_policy(sdk_config, resource_arn).await {
+        Ok(Some(resource_policy)) => Some(ResourcePolicy {
+            account: account.or(resource_policy.account),
+            ..resource_policy
+        }),
+        Ok(None) => {
+            let message = format!("[*] {} has no resource policy", resource_arn);
+            eprintln!("{}", message.bold());
+            None
+        }
+        Err(e) => exit_with_error(e),
     }
 }
 
+// `--action-name` values followed by those of `--actions-file`, without duplicates.
+fn collect_action_names(mut action_names: Vec<String>, actions_file: Option<&PathBuf>) -> Vec<String> {
+    if let Some(path) = actions_file {
+        action_names.extend(load_actions_file(path));
+    }
+    let mut seen = HashSet::new();
+    action_names.retain(|a| !a.is_empty() && seen.insert(a.to_lowercase()));
+    action_names
+}
+
+// One action per line. Blank lines and lines starting with `#` are skipped.
+fn load_actions_file(path: &PathBuf) -> Vec<String> {
+    let content = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        exit_with_error(format!("Could not read actions file {}: {}", path.display(), e))
+    });
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+fn load_auth_details(path: &PathBuf) -> AuthorizationDetails {
+    let json = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        exit_with_error(format!("Could not read authorization details {}: {}", path.display(), e))
+    });
+    AuthorizationDetails::from_json(&json).unwrap_or_else(|e| {
+        exit_with_error(format!("Could not parse authorization details {}: {}", path.display(), e))
+    })
+}
+
+fn load_request_context(pairs: &[String], file: Option<&PathBuf>) -> RequestContext {
+    let mut context = RequestContext::from_pairs(pairs).unwrap_or_else(|e| exit_with_error(e));
+
+    if let Some(path) = file {
+        let json = std::fs::read_to_string(path).unwrap_or_else(|e| {
+            exit_with_error(format!("Could not read context file {}: {}", path.display(), e))
+        });
+        let from_file = RequestContext::from_json(&json).unwrap_or_else(|e| {
+            exit_with_error(format!("Could not parse context file {}: {}", path.display(), e))
+        });
+        context.merge(from_file);
+    }
+
+    context
+}
+
+fn group_by_policy(matched: &[MatchedStatement]) -> Vec<(&String, Vec<&Statement>)> {
+    let mut grouped: Vec<(&String, Vec<&Statement>)> = Vec::new();
+    for m in matched {
+        match grouped.iter_mut().find(|(name, _)| *name == &m.policy_name) {
+            Some((_, statements)) => statements.push(&m.statement),
+            None => grouped.push((&m.policy_name, vec![&m.statement])),
+        }
+    }
+    grouped
+}
+
+fn print_verdict(evaluation: &LayeredEvaluation) {
+    let verdict = format!("[*] Final decision: {}", evaluation.decision);
+    match evaluation.decision {
+        Decision::Allowed => println!("{}", verdict.bright_green().bold()),
+        Decision::Conditional => println!("{}", verdict.bright_yellow().bold()),
+        Decision::ExplicitDeny | Decision::ImplicitDeny => println!("{}", verdict.bright_red().bold()),
+    }
+
+    match evaluation.blocking_layer() {
+        Some(layer) if layer.layer != PolicyLayer::Identity => {
+            let reason = match evaluation.decision {
+                Decision::ExplicitDeny => format!("A statement in the {} explicitly denies this action", layer.name),
+                Decision::Conditional => format!("The action is only conditionally allowed by the {}", layer.name),
+                _ => format!(
+                    "Nothing in the {} allows this action. Evaluated policies: {}",
+                    layer.name,
+                    layer.policy_names.join(", ")
+                ),
+            };
+            println!("{}", reason.bright_red().bold());
+        }
+        _ => {}
+    }
+
+    match evaluation.deciding_statement() {
+        Some(deciding) => {
+            let statement = to_string_pretty(&deciding.statement)
+                .unwrap_or_else(|_| deciding.statement.raw().to_string());
+            println!("Decided by policy {} with statement:\n{}", deciding.policy_name.bold(), statement.cyan());
+            if !deciding.missing_context_keys.is_empty() {
+                println!(
+                    "Provide these context keys to decide: {}",
+                    deciding.missing_context_keys.join(", ").bright_yellow()
+                );
+            }
+        }
+        None => println!("No statement allowed or denied this action"),
+    }
+}
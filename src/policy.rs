@@ -0,0 +1,367 @@
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use crate::arn::ArnPattern;
+use crate::glob::Glob;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Condition operators mapped to condition keys and their values.
+pub type Condition = BTreeMap<String, BTreeMap<String, Vec<String>>>;
+
+/// The policy language version that introduced policy variables. In documents
+/// of any other version, `${...}` is literal text.
+pub const VARIABLES_VERSION: &str = "2012-10-17";
+
+/// An IAM policy document.
+/// Elements that accept either a single value or an array are normalized to `Vec`s.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase", from = "RawPolicyDocument")]
+pub struct PolicyDocument {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub statement: Vec<Statement>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawPolicyDocument {
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(deserialize_with = "one_or_many")]
+    statement: Vec<Statement>,
+}
+
+impl From<RawPolicyDocument> for PolicyDocument {
+    fn from(raw: RawPolicyDocument) -> Self {
+        let supports_variables = raw.version.as_deref() == Some(VARIABLES_VERSION);
+        let statement = raw
+            .statement
+            .into_iter()
+            .map(|statement| Statement {
+                supports_variables,
+                ..statement
+            })
+            .collect();
+        PolicyDocument {
+            version: raw.version,
+            id: raw.id,
+            statement,
+        }
+    }
+}
+
+impl FromStr for PolicyDocument {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Effect {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Principal {
+    /// `"Principal": "*"`
+    Any,
+    /// Principal types (`AWS`, `Service`, `Federated`, `CanonicalUser`) mapped to their values.
+    Typed(BTreeMap<String, Vec<String>>),
+}
+
+/// A policy statement. It serializes back to the JSON it was read from,
+/// so output shows statements exactly as written in the policy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub sid: Option<String>,
+    pub effect: Effect,
+    pub action: Option<Vec<String>>,
+    pub not_action: Option<Vec<String>>,
+    pub resource: Option<Vec<String>>,
+    pub not_resource: Option<Vec<String>>,
+    pub principal: Option<Principal>,
+    pub not_principal: Option<Principal>,
+    pub condition: Option<Condition>,
+    raw: Value,
+    action_patterns: Vec<Glob>,
+    resource_patterns: Vec<ArnPattern>,
+    supports_variables: bool,
+}
+
+impl Statement {
+    pub fn raw(&self) -> &Value {
+        &self.raw
+    }
+
+    /// Compiled patterns of `Action`, or of `NotAction` when that is used instead.
+    pub fn action_patterns(&self) -> &[Glob] {
+        &self.action_patterns
+    }
+
+    /// Compiled patterns of `Resource`, or of `NotResource` when that is used instead.
+    pub fn resource_patterns(&self) -> &[ArnPattern] {
+        &self.resource_patterns
+    }
+
+    /// Whether policy variables in `Resource` and `Condition` values are
+    /// resolved, which depends on the `Version` of the document.
+    pub fn supports_variables(&self) -> bool {
+        self.supports_variables
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawStatement {
+    #[serde(default)]
+    sid: Option<String>,
+    effect: Effect,
+    #[serde(default, deserialize_with = "optional_one_or_many")]
+    action: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "optional_one_or_many")]
+    not_action: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "optional_one_or_many")]
+    resource: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "optional_one_or_many")]
+    not_resource: Option<Vec<String>>,
+    #[serde(default)]
+    principal: Option<Value>,
+    #[serde(default)]
+    not_principal: Option<Value>,
+    #[serde(default)]
+    condition: Option<BTreeMap<String, BTreeMap<String, Value>>>,
+}
+
+impl<'de> Deserialize<'de> for Statement {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = Value::deserialize(deserializer)?;
+        let parsed: RawStatement = serde_json::from_value(raw.clone()).map_err(de::Error::custom)?;
+
+        if parsed.action.is_some() == parsed.not_action.is_some() {
+            return Err(de::Error::custom(
+                "a statement needs exactly one of Action or NotAction",
+            ));
+        }
+
+        let condition = parsed.condition.map(|operators| {
+            operators
+                .into_iter()
+                .map(|(operator, keys)| {
+                    let keys = keys
+                        .into_iter()
+                        .map(|(key, value)| (key, values_of(&value)))
+                        .collect();
+                    (operator, keys)
+                })
+                .collect()
+        });
+
+        let action_patterns = parsed
+            .action
+            .iter()
+            .chain(parsed.not_action.iter())
+            .flatten()
+            .map(|a| Glob::ignore_case(a))
+            .collect();
+        let resource_patterns = parsed
+            .resource
+            .iter()
+            .chain(parsed.not_resource.iter())
+            .flatten()
+            .map(|r| ArnPattern::new(r))
+            .collect();
+
+        Ok(Statement {
+            sid: parsed.sid,
+            effect: parsed.effect,
+            action: parsed.action,
+            not_action: parsed.not_action,
+            resource: parsed.resource,
+            not_resource: parsed.not_resource,
+            principal: parsed.principal.map(parse_principal).transpose().map_err(de::Error::custom)?,
+            not_principal: parsed.not_principal.map(parse_principal).transpose().map_err(de::Error::custom)?,
+            condition,
+            raw,
+            action_patterns,
+            resource_patterns,
+            // Set once the document's version is known.
+            supports_variables: false,
+        })
+    }
+}
+
+impl Serialize for Statement {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.raw.serialize(serializer)
+    }
+}
+
+fn parse_principal(value: Value) -> Result<Principal, String> {
+    match value {
+        Value::String(s) if s == "*" => Ok(Principal::Any),
+        Value::Object(map) => Ok(Principal::Typed(
+            map.into_iter()
+                .map(|(kind, v)| (kind, values_of(&v)))
+                .collect(),
+        )),
+        v => Err(format!("'{}' is not a valid principal", v)),
+    }
+}
+
+fn values_of(value: &Value) -> Vec<String> {
+    match value {
+        Value::Array(arr) => arr.iter().map(value_to_string).collect(),
+        v => vec![value_to_string(v)],
+    }
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+// Deserializes an element that holds either a single value or an array of them.
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    match Value::deserialize(deserializer)? {
+        Value::Array(arr) => arr
+            .into_iter()
+            .map(|v| serde_json::from_value(v).map_err(de::Error::custom))
+            .collect(),
+        v => Ok(vec![serde_json::from_value(v).map_err(de::Error::custom)?]),
+    }
+}
+
+fn optional_one_or_many<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    one_or_many(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::policy;
+
+    fn statement(json: &str) -> Statement {
+        policy(&format!("[{}]", json)).statement.remove(0)
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn single_object_statement_is_a_list_of_one() {
+        let json = r#"{"Version":"2012-10-17","Statement":{"Effect":"Allow","Action":"s3:GetObject","Resource":"*"}}"#;
+        let document: PolicyDocument = json.parse().unwrap();
+        assert_eq!(document.statement.len(), 1);
+        assert_eq!(document.statement[0].action, Some(strings(&["s3:GetObject"])));
+    }
+
+    #[test]
+    fn action_and_resource_take_a_string_or_an_array() {
+        let single = statement(r#"{"Effect":"Allow","Action":"s3:GetObject","Resource":"arn:aws:s3:::a/*"}"#);
+        assert_eq!(single.action, Some(strings(&["s3:GetObject"])));
+        assert_eq!(single.resource, Some(strings(&["arn:aws:s3:::a/*"])));
+
+        let many = statement(
+            r#"{"Effect":"Allow","Action":["s3:GetObject","s3:PutObject"],"Resource":["arn:aws:s3:::a","arn:aws:s3:::a/*"]}"#,
+        );
+        assert_eq!(many.action, Some(strings(&["s3:GetObject", "s3:PutObject"])));
+        assert_eq!(many.resource, Some(strings(&["arn:aws:s3:::a", "arn:aws:s3:::a/*"])));
+        assert_eq!(many.action_patterns().len(), 2);
+        assert_eq!(many.resource_patterns().len(), 2);
+    }
+
+    #[test]
+    fn principal_is_any_or_typed() {
+        let any = statement(r#"{"Effect":"Allow","Principal":"*","Action":"s3:GetObject"}"#);
+        assert_eq!(any.principal, Some(Principal::Any));
+
+        let aws = statement(
+            r#"{"Effect":"Allow","Principal":{"AWS":"arn:aws:iam::123456789012:root"},"Action":"s3:GetObject"}"#,
+        );
+        let expected = BTreeMap::from([("AWS".to_string(), strings(&["arn:aws:iam::123456789012:root"]))]);
+        assert_eq!(aws.principal, Some(Principal::Typed(expected)));
+
+        let service = statement(
+            r#"{"Effect":"Allow","Principal":{"Service":["lambda.amazonaws.com","ec2.amazonaws.com"]},"Action":"sts:AssumeRole"}"#,
+        );
+        let expected = BTreeMap::from([(
+            "Service".to_string(),
+            strings(&["lambda.amazonaws.com", "ec2.amazonaws.com"]),
+        )]);
+        assert_eq!(service.principal, Some(Principal::Typed(expected)));
+
+        let invalid = r#"{"Statement":[{"Effect":"Allow","Principal":"alice","Action":"s3:GetObject"}]}"#;
+        assert!(invalid.parse::<PolicyDocument>().is_err());
+    }
+
+    #[test]
+    fn not_principal_is_parsed_apart_from_principal() {
+        let parsed = statement(
+            r#"{"Effect":"Deny","NotPrincipal":{"AWS":"arn:aws:iam::123456789012:role/admin"},"Action":"s3:*"}"#,
+        );
+        assert_eq!(parsed.principal, None);
+        let expected = BTreeMap::from([("AWS".to_string(), strings(&["arn:aws:iam::123456789012:role/admin"]))]);
+        assert_eq!(parsed.not_principal, Some(Principal::Typed(expected)));
+    }
+
+    #[test]
+    fn statement_needs_exactly_one_of_action_or_not_action() {
+        let both = r#"{"Statement":[{"Effect":"Allow","Action":"s3:*","NotAction":"iam:*","Resource":"*"}]}"#;
+        let neither = r#"{"Statement":[{"Effect":"Allow","Resource":"*"}]}"#;
+        for json in [both, neither] {
+            let error = json.parse::<PolicyDocument>().unwrap_err();
+            assert!(
+                error.to_string().contains("exactly one of Action or NotAction"),
+                "{}",
+                error
+            );
+        }
+
+        let not_action = statement(r#"{"Effect":"Allow","NotAction":"iam:*","Resource":"*"}"#);
+        assert_eq!(not_action.action, None);
+        assert_eq!(not_action.not_action, Some(strings(&["iam:*"])));
+        assert_eq!(not_action.action_patterns().len(), 1);
+    }
+
+    #[test]
+    fn only_the_2012_10_17_version_supports_variables() {
+        let statements = r#"[{"Effect":"Allow","Action":"s3:*","Resource":"arn:aws:s3:::${aws:username}/*"}]"#;
+        let document = |version: &str| -> PolicyDocument {
+            format!(r#"{{{}"Statement":{}}}"#, version, statements).parse().unwrap()
+        };
+
+        assert!(document(r#""Version":"2012-10-17","#).statement[0].supports_variables());
+        assert!(!document(r#""Version":"2008-10-17","#).statement[0].supports_variables());
+        assert!(!document("").statement[0].supports_variables());
+    }
+
+    #[test]
+    fn statements_serialize_as_written() {
+        let json = r#"{"Sid":"Read","Effect":"Allow","Action":"s3:GetObject","Resource":"*"}"#;
+        let parsed = statement(json);
+        assert_eq!(
+            serde_json::to_value(&parsed).unwrap(),
+            serde_json::from_str::<Value>(json).unwrap()
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use crate::catalog::{AccessLevel, ActionCatalog};
+use crate::diff::ActionDiff;
+use crate::effective::{AllowedAction, Expansion};
+use crate::evaluation::{Decision, LayeredEvaluation, PolicyLayer, Request};
+use crate::policy::{Effect, Statement};
+use crate::source::{Entity, EntityKind, EntityPolicies, PolicyType, PolicyWarning};
+use crate::trust::{analyze_trust_policy, TrustGrant};
+use crate::verify::Verification;
+use serde::Serialize;
+
+/// Bumped whenever a field is removed or changes meaning. Adding fields is
+/// not a breaking change.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The result of checking one action for one entity, as printed by `--output json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DecisionReport {
+    pub schema_version: u32,
+    pub entity: EntityReport,
+    pub resource: Option<String>,
+    #[serde(flatten)]
+    pub result: ActionResult,
+    /// What the role's trust policy allows and denies. Users and groups have none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trust: Option<Vec<TrustGrant>>,
+}
+
+/// The results of checking several actions for one entity.
+#[derive(Debug, Clone, Serialize)]
+pub struct ActionMatrixReport {
+    pub schema_version: u32,
+    pub entity: EntityReport,
+    pub resource: Option<String>,
+    pub results: Vec<ActionResult>,
+    /// What the role's trust policy allows and denies. Users and groups have none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trust: Option<Vec<TrustGrant>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActionResult {
+    pub action: String,
+    pub decision: Decision,
+    /// The layer that denied, or failed to allow, the action.
+    pub blocking_layer: Option<String>,
+    pub matched_statements: Vec<StatementReport>,
+    /// The IAM policy simulator's result, with `--verify`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification: Option<Verification>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntityReport {
+    #[serde(rename = "type")]
+    pub kind: EntityKind,
+    pub name: String,
+    pub arn: Option<String>,
+    /// Policies that could not be read. When non-empty, a deny may be wrong.
+    pub warnings: Vec<PolicyWarning>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatementReport {
+    pub layer: String,
+    pub policy_name: String,
+    pub policy_arn: Option<String>,
+    pub policy_type: PolicyType,
+    pub sid: Option<String>,
+    pub effect: Effect,
+    /// Non-empty when the statement only conditionally applies.
+    pub missing_context_keys: Vec<String>,
+    pub statement: Statement,
+}
+
+/// Every user and role allowed an action, as printed by `who-can --output json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct WhoCanReport {
+    pub schema_version: u32,
+    pub action: String,
+    pub resource: Option<String>,
+    pub principals: Vec<DecisionReport>,
+    /// Principals denied the action with policies that could not be read,
+    /// who may actually be allowed.
+    pub incomplete_principals: Vec<EntityReport>,
+}
+
+/// The actions two entities get different decisions for, as printed by `diff --output json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffReport {
+    pub schema_version: u32,
+    pub left: EntityReport,
+    pub right: EntityReport,
+    pub resource: Option<String>,
+    pub differences: Vec<ActionDiffReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActionDiffReport {
+    pub action: String,
+    pub left: ActionResult,
+    pub right: ActionResult,
+}
+
+/// The effective permissions of an entity, as printed by `list-allowed --output json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AllowedActionsReport {
+    pub schema_version: u32,
+    pub entity: EntityReport,
+    pub resource: Option<String>,
+    pub actions: Vec<AllowedActionReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AllowedActionReport {
+    pub action: String,
+    /// `None` for actions of services missing from the bundled catalog.
+    pub access_level: Option<AccessLevel>,
+    pub decision: Decision,
+    pub policy_name: String,
+    pub sid: Option<String>,
+    pub statement: Option<Statement>,
+    pub expansion: Expansion,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub excludes: Vec<String>,
+}
+
+impl EntityReport {
+    pub fn new(entity: &Entity, policies: &EntityPolicies) -> Self {
+        EntityReport {
+            kind: entity.kind,
+            name: entity.name.clone(),
+            arn: policies.arn.clone(),
+            warnings: policies.warnings.clone(),
+        }
+    }
+}
+
+impl DecisionReport {
+    pub fn new(
+        entity: &Entity,
+        policies: &EntityPolicies,
+        request: &Request,
+        evaluation: &LayeredEvaluation,
+    ) -> Self {
+        DecisionReport {
+            schema_version: SCHEMA_VERSION,
+            entity: EntityReport::new(entity, policies),
+            resource: request.resource_arn.clone(),
+            result: ActionResult::new(policies, request, evaluation),
+            trust: trust_report(policies),
+        }
+    }
+}
+
+impl ActionMatrixReport {
+    /// `evaluations` holds the evaluation of each of `action_names`, in order.
+    pub fn new(
+        entity: &Entity,
+        policies: &EntityPolicies,
+        request: &Request,
+        action_names: &[String],
+        evaluations: &[LayeredEvaluation],
+    ) -> Self {
+        let results = action_names
+            .iter()
+            .zip(evaluations)
+            .map(|(action_name, evaluation)| {
+                let request = Request {
+                    action_name: action_name.clone(),
+                    ..request.clone()
+                };
+                ActionResult::new(policies, &request, evaluation)
+            })
+            .collect();
+
+        ActionMatrixReport {
+            schema_version: SCHEMA_VERSION,
+            entity: EntityReport::new(entity, policies),
+            resource: request.resource_arn.clone(),
+            results,
+            trust: trust_report(policies),
+        }
+    }
+}
+
+fn trust_report(policies: &EntityPolicies) -> Option<Vec<TrustGrant>> {
+    policies.trust_policy.as_ref().map(analyze_trust_policy)
+}
+
+impl ActionResult {
+    pub fn new(policies: &EntityPolicies, request: &Request, evaluation: &LayeredEvaluation) -> Self {
+        let mut matched_statements = vec![];
+        for layer in &evaluation.layers {
+            let e = &layer.evaluation;
+            for matched in e.allowed.iter().chain(&e.denied).chain(&e.conditional) {
+                let (policy_type, policy_arn) = match layer.layer {
+                    PolicyLayer::Identity => match policies.identity_origins.get(matched.policy_index) {
+                        Some(origin) => (origin.policy_type, origin.arn.clone()),
+                        None => (PolicyType::Inline, None),
+                    },
+                    PolicyLayer::ResourcePolicy => (PolicyType::Resource, request.resource_arn.clone()),
+                    PolicyLayer::PermissionsBoundary => {
+                        (PolicyType::Boundary, policies.permissions_boundary_arn.clone())
+                    }
+                    PolicyLayer::SessionPolicy => match policies.session_policy_origins.get(matched.policy_index) {
+                        Some(origin) => (origin.policy_type, origin.arn.clone()),
+                        None => (PolicyType::Session, None),
+                    },
+                    PolicyLayer::ServiceControlPolicy => (PolicyType::Scp, None),
+                };
+                matched_statements.push(StatementReport {
+                    layer: layer.name.clone(),
+                    policy_name: matched.policy_name.clone(),
+                    policy_arn,
+                    policy_type,
+                    sid: matched.statement.sid.clone(),
+                    effect: matched.statement.effect,
+                    missing_context_keys: matched.missing_context_keys.clone(),
+                    statement: matched.statement.clone(),
+                });
+            }
+        }
+
+        ActionResult {
+            action: request.action_name.clone(),
+            decision: evaluation.decision,
+            blocking_layer: evaluation.blocking_layer().map(|l| l.name.clone()),
+            matched_statements,
+            verification: None,
+        }
+    }
+}
+
+impl DiffReport {
+    pub fn new(
+        (left, left_policies): (&Entity, &EntityPolicies),
+        (right, right_policies): (&Entity, &EntityPolicies),
+        request: &Request,
+        diffs: &[ActionDiff],
+    ) -> Self {
+        let differences = diffs
+            .iter()
+            .map(|diff| {
+                let request = Request {
+                    action_name: diff.action.clone(),
+                    ..request.clone()
+                };
+                ActionDiffReport {
+                    action: diff.action.clone(),
+                    left: ActionResult::new(left_policies, &request, &diff.left),
+                    right: ActionResult::new(right_policies, &request, &diff.right),
+                }
+            })
+            .collect();
+
+        DiffReport {
+            schema_version: SCHEMA_VERSION,
+            left: EntityReport::new(left, left_policies),
+            right: EntityReport::new(right, right_policies),
+            resource: request.resource_arn.clone(),
+            differences,
+        }
+    }
+}
+
+impl AllowedActionsReport {
+    pub fn new(
+        entity: &Entity,
+        policies: &EntityPolicies,
+        request: &Request,
+        allowed: &[AllowedAction],
+        catalog: &ActionCatalog,
+    ) -> Self {
+        let actions = allowed
+            .iter()
+            .map(|a| AllowedActionReport {
+                action: a.action.clone(),
+                access_level: catalog.action(&a.action).map(|(_, action)| action.access_level),
+                decision: a.decision,
+                policy_name: a.policy_name.clone(),
+                sid: a.statement.as_ref().and_then(|s| s.sid.clone()),
+                statement: a.statement.clone(),
+                expansion: a.expansion,
+                excludes: a.excludes.clone(),
+            })
+            .collect();
+
+        AllowedActionsReport {
+            schema_version: SCHEMA_VERSION,
+            entity: EntityReport::new(entity, policies),
+            resource: request.resource_arn.clone(),
+            actions,
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use crate::evaluation::{LayerEvaluation, PolicyLayer, Request};
+use crate::policy::{Effect, PolicyDocument, Principal, Statement};
+use crate::error::Error;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// A policy attached to the resource itself, such as a bucket policy or a key policy.
+#[derive(Debug, Clone)]
+pub struct ResourcePolicy {
+    pub name: String,
+    pub document: PolicyDocument,
+    /// Account that owns the resource, when known.
+    pub account: Option<String>,
+}
+
+/// How a statement's `Principal` covers the entity making the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrincipalMatch {
+    /// The entity is named by its ARN, by `*`, or is not excluded by `NotPrincipal`.
+    Direct,
+    /// Only the entity's account is named, which delegates the decision to
+    /// the account's identity policies.
+    Account,
+}
+
+impl ResourcePolicy {
+    /// Evaluates the statements whose principal covers `principal_arn`.
+    /// Allow statements that only name the account are skipped unless
+    /// `include_account_allows` is set, as they grant nothing by themselves.
+    pub fn evaluate(
+        &self,
+        principal_arn: Option<&str>,
+        request: &Request,
+        include_account_allows: bool,
+    ) -> LayerEvaluation {
+        let statement = self
+            .document
+            .statement
+            .iter()
+            .filter(|statement| match match_principal(statement, principal_arn) {
+                Some(PrincipalMatch::Direct) => true,
+                Some(PrincipalMatch::Account) => {
+                    statement.effect == Effect::Deny || include_account_allows
+                }
+                None => false,
+            })
+            .cloned()
+            .collect();
+        let document = PolicyDocument {
+            statement,
+            ..self.document.clone()
+        };
+
+        LayerEvaluation::evaluate(
+            PolicyLayer::ResourcePolicy,
+            format!("{} {}", PolicyLayer::ResourcePolicy, self.name),
+            &[(self.name.clone(), document)],
+            request,
+        )
+    }
+}
+
+pub fn match_principal(statement: &Statement, principal_arn: Option<&str>) -> Option<PrincipalMatch> {
+    if let Some(principal) = &statement.principal {
+        principal_covers(principal, principal_arn)
+    } else if let Some(not_principal) = &statement.not_principal {
+        match principal_covers(not_principal, principal_arn) {
+            Some(_) => None,
+            None => Some(PrincipalMatch::Direct),
+        }
+    } else {
+        None
+    }
+}
+
+fn principal_covers(principal: &Principal, principal_arn: Option<&str>) -> Option<PrincipalMatch> {
+    let values = match principal {
+        Principal::Any => return Some(PrincipalMatch::Direct),
+        Principal::Typed(types) => types.get("AWS")?,
+    };
+    if values.iter().any(|v| v == "*") {
+        return Some(PrincipalMatch::Direct);
+    }
+
+    let principal_arn = principal_arn?;
+    if values.iter().any(|v| v == principal_arn) {
+        return Some(PrincipalMatch::Direct);
+    }
+
+    let account = account_of(principal_arn)?;
+    let partition = principal_arn.split(':').nth(1)?;
+    let root = format!("arn:{}:iam::{}:root", partition, account);
+    values
+        .iter()
+        .any(|v| v == account || *v == root)
+        .then_some(PrincipalMatch::Account)
+}
+
+/// The account segment of an ARN, if it has one.
+pub fn account_of(arn: &str) -> Option<&str> {
+    arn.split(':').nth(4).filter(|account| !account.is_empty())
+}
+
+// Accepts a plain policy document, or the output of the CLI command that reads
+// the policy: `s3api get-bucket-policy`, `kms get-key-policy`, `lambda get-policy`,
+// `secretsmanager get-resource-policy`, `sqs get-queue-attributes` or
+// `sns get-topic-attributes`, which all wrap the document as a string.
+pub fn load_resource_policy(path: &Path, account: Option<String>) -> Result<ResourcePolicy, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read resource policy file {}: {}", path.display(), e))?;
+    let json: Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Could not parse resource policy file {}: {}", path.display(), e))?;
+
+    let wrapped = json
+        .get("Policy")
+        .or_else(|| json.get("ResourcePolicy"))
+        .or_else(|| json.pointer("/Attributes/Policy"))
+        .and_then(|p| p.as_str());
+    let document: PolicyDocument = match wrapped {
+        Some(document) => document.parse(),
+        None => serde_json::from_value(json),
+    }
+    .map_err(|e| format!("Could not parse resource policy in {}: {}", path.display(), e))?;
+
+    let name = path
+        .file_stem()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(ResourcePolicy {
+        name,
+        document,
+        account,
+    })
+}
+
+// Reads the policy attached to `resource_arn` from its service.
+// Returns `None` when the resource has no policy.
+pub async fn fetch_resource_policy(
+    sdk_config: &aws_config::SdkConfig,
+    resource_arn: &str,
+) -> Result<Option<ResourcePolicy>, Error> {
+    let service = resource_arn.split(':').nth(2).unwrap_or_default();
+    let policy = match service {
+        "s3" => fetch::s3(sdk_config, resource_arn).await?,
+        "kms" => fetch::kms(sdk_config, resource_arn).await?,
+        "sqs" => fetch::sqs(sdk_config, resource_arn).await?,
+        "sns" => fetch::sns(sdk_config, resource_arn).await?,
+        "lambda" => fetch::lambda(sdk_config, resource_arn).await?,
+        "secretsmanager" => fetch::secretsmanager(sdk_config, resource_arn).await?,
+        _ => return Err(format!("Fetching resource policies is not supported for '{}'", service).into()),
+    };
+
+    let Some(document) = policy else {
+        return Ok(None);
+    };
+    Ok(Some(ResourcePolicy {
+        name: resource_arn.to_string(),
+        document: document.parse().map_err(|e: serde_json::Error| Error::PolicyParse {
+            policy: resource_arn.to_string(),
+            message: e.to_string(),
+        })?,
+        account: account_of(resource_arn).map(|a| a.to_string()),
+    }))
+}
+
+// Each fetcher returns the policy document as the service stores it. Services
+// whose cargo feature is disabled get a stub that reports it.
+mod fetch {
+    use crate::error::Error;
+    #[cfg(any(feature = "kms", feature = "sqs", feature = "sns", feature = "lambda", feature = "secretsmanager"))]
+    use aws_config::Region;
+    use aws_config::SdkConfig;
+
+    // The region segment of an ARN. Clients call the region the resource is
+    // in, and ARNs without one keep the configured region.
+    #[cfg(any(feature = "kms", feature = "sqs", feature = "sns", feature = "lambda", feature = "secretsmanager"))]
+    fn region_of(arn: &str, config: &SdkConfig) -> Option<Region> {
+        match arn.split(':').nth(3).filter(|region| !region.is_empty()) {
+            Some(region) => Some(Region::new(region.to_string())),
+            None => config.region().cloned(),
+        }
+    }
+
+    // The resource segment of an ARN, after the account.
+    #[cfg(any(feature = "s3", feature = "sqs"))]
+    fn resource_of(arn: &str) -> &str {
+        arn.splitn(6, ':').nth(5).unwrap_or_default()
+    }
+
+    #[cfg(feature = "s3")]
+    pub async fn s3(config: &SdkConfig, arn: &str) -> Result<Option<String>, Error> {
+        let client = aws_sdk_s3::Client::new(config);
+        let bucket = resource_of(arn).split('/').next().unwrap_or_default();
+        match client.get_bucket_policy().bucket(bucket).send().await {
+            Ok(response) => Ok(response.policy),
+            Err(e) if e.as_service_error().and_then(|e| e.meta().code()) == Some("NoSuchBucketPolicy") => {
+                Ok(None)
+            }
+            Err(e) => Err(Error::from_sdk("s3:GetBucketPolicy", None, e)),
+        }
+    }
+
+    #[cfg(feature = "kms")]
+    pub async fn kms(config: &SdkConfig, arn: &str) -> Result<Option<String>, Error> {
+        let regional = aws_sdk_kms::config::Builder::from(config).region(region_of(arn, config)).build();
+        let client = aws_sdk_kms::Client::from_conf(regional);
+        let response = client
+            .get_key_policy()
+            .key_id(arn)
+            .policy_name("default")
+            .send()
+            .await
+            .map_err(|e| Error::from_sdk("kms:GetKeyPolicy", None, e))?;
+        Ok(response.policy)
+    }
+
+    #[cfg(feature = "sqs")]
+    pub async fn sqs(config: &SdkConfig, arn: &str) -> Result<Option<String>, Error> {
+        use aws_sdk_sqs::types::QueueAttributeName;
+
+        let regional = aws_sdk_sqs::config::Builder::from(config).region(region_of(arn, config)).build();
+        let client = aws_sdk_sqs::Client::from_conf(regional);
+        let queue_url = client
+            .get_queue_url()
+            .queue_name(resource_of(arn))
+            .set_queue_owner_aws_account_id(super::account_of(arn).map(|a| a.to_string()))
+            .send()
+            .await
+            .map_err(|e| Error::from_sdk("sqs:GetQueueUrl", None, e))?
+            .queue_url
+            .ok_or_else(|| format!("queue {} has no URL", arn))?;
+        let attributes = client
+            .get_queue_attributes()
+            .queue_url(queue_url)
+            .attribute_names(QueueAttributeName::Policy)
+            .send()
+            .await
+            .map_err(|e| Error::from_sdk("sqs:GetQueueAttributes", None, e))?
+            .attributes;
+        Ok(attributes.and_then(|mut a| a.remove(&QueueAttributeName::Policy)))
+    }
+
+    #[cfg(feature = "sns")]
+    pub async fn sns(config: &SdkConfig, arn: &str) -> Result<Option<String>, Error> {
+        let regional = aws_sdk_sns::config::Builder::from(config).region(region_of(arn, config)).build();
+        let client = aws_sdk_sns::Client::from_conf(regional);
+        let attributes = client
+            .get_topic_attributes()
+            .topic_arn(arn)
+            .send()
+            .await
+            .map_err(|e| Error::from_sdk("sns:GetTopicAttributes", None, e))?
+            .attributes;
+        Ok(attributes.and_then(|mut a| a.remove("Policy")))
+    }
+
+    #[cfg(feature = "lambda")]
+    pub async fn lambda(config: &SdkConfig, arn: &str) -> Result<Option<String>, Error> {
+        let regional = aws_sdk_lambda::config::Builder::from(config).region(region_of(arn, config)).build();
+        let client = aws_sdk_lambda::Client::from_conf(regional);
+        let response = client
+            .get_policy()
+            .function_name(arn)
+            .send()
+            .await
+            .map_err(|e| Error::from_sdk("lambda:GetPolicy", None, e))?;
+        Ok(response.policy)
+    }
+
+    #[cfg(feature = "secretsmanager")]
+    pub async fn secretsmanager(config: &SdkConfig, arn: &str) -> Result<Option<String>, Error> {
+        let regional = aws_sdk_secretsmanager::config::Builder::from(config).region(region_of(arn, config)).build();
+        let client = aws_sdk_secretsmanager::Client::from_conf(regional);
+        let response = client
+            .get_resource_policy()
+            .secret_id(arn)
+            .send()
+            .await
+            .map_err(|e| Error::from_sdk("secretsmanager:GetResourcePolicy", None, e))?;
+        Ok(response.resource_policy)
+    }
+
+    #[cfg(not(feature = "s3"))]
+    pub async fn s3(_config: &SdkConfig, _arn: &str) -> Result<Option<String>, Error> {
+        Err("which-allowed was built without the 's3' feature, so it cannot fetch this resource policy".into())
+    }
+
+    #[cfg(not(feature = "kms"))]
+    pub async fn kms(_config: &SdkConfig, _arn: &str) -> Result<Option<String>, Error> {
+        Err("which-allowed was built without the 'kms' feature, so it cannot fetch this resource policy".into())
+    }
+
+    #[cfg(not(feature = "sqs"))]
+    pub async fn sqs(_config: &SdkConfig, _arn: &str) -> Result<Option<String>, Error> {
+        Err("which-allowed was built without the 'sqs' feature, so it cannot fetch this resource policy".into())
+    }
+
+    #[cfg(not(feature = "sns"))]
+    pub async fn sns(_config: &SdkConfig, _arn: &str) -> Result<Option<String>, Error> {
+        Err("which-allowed was built without the 'sns' feature, so it cannot fetch this resource policy".into())
+    }
+
+    #[cfg(not(feature = "lambda"))]
+    pub async fn lambda(_config: &SdkConfig, _arn: &str) -> Result<Option<String>, Error> {
+        Err("which-allowed was built without the 'lambda' feature, so it cannot fetch this resource policy".into())
+    }
+
+    #[cfg(not(feature = "secretsmanager"))]
+    pub async fn secretsmanager(_config: &SdkConfig, _arn: &str) -> Result<Option<String>, Error> {
+        Err("which-allowed was built without the 'secretsmanager' feature, so it cannot fetch this resource policy".into())
+    }
+}
@@ -0,0 +1,220 @@
+use crate::evaluation::NamedPolicy;
+use crate::policy::PolicyDocument;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The SCPs attached at one point of the organization (root, an OU or the account).
+/// The action has to be allowed at every level, and is allowed at a level
+/// when any of its SCPs allows it.
+#[derive(Debug, Clone)]
+pub struct ScpLevel {
+    pub name: String,
+    pub policies: Vec<NamedPolicy>,
+}
+
+// Files given directly form one level. A directory is read as an exported OU
+// hierarchy: its JSON files are the root's SCPs, and its single subdirectory
+// holds the next level down, until the account. Directories without JSON
+// files above the root are skipped.
+pub fn load_scps(paths: &[PathBuf]) -> Result<Vec<ScpLevel>, String> {
+    let mut levels = vec![];
+
+    let files: Vec<&PathBuf> = paths.iter().filter(|p| !p.is_dir()).collect();
+    if !files.is_empty() {
+        let policies = files
+            .iter()
+            .map(|path| load_scp_file(path))
+            .collect::<Result<Vec<_>, String>>()?;
+        levels.push(ScpLevel {
+            name: "--scp files".to_string(),
+            policies,
+        });
+    }
+
+    for dir in paths.iter().filter(|p| p.is_dir()) {
+        load_hierarchy(dir, String::new(), &mut levels)?;
+    }
+
+    Ok(levels)
+}
+
+fn load_hierarchy(dir: &Path, parent: String, levels: &mut Vec<ScpLevel>) -> Result<(), String> {
+    let dir_name = dir
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| dir.display().to_string());
+    let name = if parent.is_empty() {
+        dir_name
+    } else {
+        format!("{}/{}", parent, dir_name)
+    };
+
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("Could not read SCP directory {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect();
+    entries.sort();
+
+    let policies = entries
+        .iter()
+        .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+        .map(|p| load_scp_file(p))
+        .collect::<Result<Vec<_>, String>>()?;
+    let children: Vec<&PathBuf> = entries.iter().filter(|p| p.is_dir()).collect();
+
+    // Every level has at least one SCP attached, so a directory without any
+    // only wraps the hierarchy, like `scps/` around `scps/root/`.
+    if policies.is_empty() {
+        return match children.as_slice() {
+            [child] => load_hierarchy(child, parent, levels),
+            [] => Err(format!("SCP directory {} has no policy files", dir.display())),
+            _ => Err(format!(
+                "SCP directory {} has no policy files and more than one subdirectory. Pass the directory of the organization root.",
+                dir.display()
+            )),
+        };
+    }
+
+    levels.push(ScpLevel {
+        name: name.clone(),
+        policies,
+    });
+
+    match children.as_slice() {
+        [] => Ok(()),
+        [child] => load_hierarchy(child, name, levels),
+        _ => Err(format!(
+            "SCP directory {} has more than one child OU. Export only the path down to the account.",
+            dir.display()
+        )),
+    }
+}
+
+// Accepts a plain policy document, or the output of
+// `aws organizations describe-policy`, which wraps the document as a string.
+fn load_scp_file(path: &Path) -> Result<NamedPolicy, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read SCP file {}: {}", path.display(), e))?;
+    let json: Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Could not parse SCP file {}: {}", path.display(), e))?;
+
+    let file_name = path
+        .file_stem()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    match json.get("Policy") {
+        Some(policy) => {
+            let name = policy
+                .pointer("/PolicySummary/Name")
+                .and_then(|n| n.as_str())
+                .map(|n| n.to_string())
+                .unwrap_or(file_name);
+            let document = policy
+                .get("Content")
+                .and_then(|c| c.as_str())
+                .ok_or_else(|| format!("SCP file {} has no Policy.Content", path.display()))?;
+            let document: PolicyDocument = document
+                .parse()
+                .map_err(|e| format!("Could not parse SCP content in {}: {}", path.display(), e))?;
+            Ok((name, document))
+        }
+        None => {
+            let document: PolicyDocument = serde_json::from_value(json)
+                .map_err(|e| format!("Could not parse SCP file {}: {}", path.display(), e))?;
+            Ok((file_name, document))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALLOW_ALL: &str = r#"{"Version":"2012-10-17","Statement":[{"Effect":"Allow","Action":"*","Resource":"*"}]}"#;
+
+    // A directory under the system temp directory, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("which-allowed-scp-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        // Writes `content` to `relative`, creating its directories.
+        fn write(&self, relative: &str, content: &str) -> PathBuf {
+            let path = self.0.join(relative);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, content).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn names(levels: &[ScpLevel]) -> Vec<&str> {
+        levels.iter().map(|level| level.name.as_str()).collect()
+    }
+
+    #[test]
+    fn files_form_one_level() {
+        let dir = TempDir::new("files");
+        let described = serde_json::json!({
+            "Policy": {"PolicySummary": {"Name": "DenyRegions"}, "Content": ALLOW_ALL}
+        });
+        let paths = [dir.write("full-access.json", ALLOW_ALL), dir.write("described.json", &described.to_string())];
+
+        let levels = load_scps(&paths).unwrap();
+        assert_eq!(names(&levels), ["--scp files"]);
+        let policy_names: Vec<&str> = levels[0].policies.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(policy_names, ["full-access", "DenyRegions"]);
+    }
+
+    #[test]
+    fn nested_directories_are_levels_down_to_the_account() {
+        let dir = TempDir::new("nested");
+        dir.write("root/FullAWSAccess.json", ALLOW_ALL);
+        dir.write("root/workloads/DenyLeaving.json", ALLOW_ALL);
+        dir.write("root/workloads/DenyRegions.json", ALLOW_ALL);
+        dir.write("root/workloads/123456789012/FullAWSAccess.json", ALLOW_ALL);
+
+        let levels = load_scps(&[dir.0.join("root")]).unwrap();
+        assert_eq!(names(&levels), ["root", "root/workloads", "root/workloads/123456789012"]);
+        assert_eq!(levels[1].policies.len(), 2);
+    }
+
+    #[test]
+    fn directories_without_policies_above_the_root_are_skipped() {
+        let dir = TempDir::new("wrapped");
+        dir.write("export/root/FullAWSAccess.json", ALLOW_ALL);
+        dir.write("export/root/123456789012/FullAWSAccess.json", ALLOW_ALL);
+
+        let levels = load_scps(std::slice::from_ref(&dir.0)).unwrap();
+        assert_eq!(names(&levels), ["root", "root/123456789012"]);
+    }
+
+    #[test]
+    fn more_than_one_child_ou_is_an_error() {
+        let dir = TempDir::new("siblings");
+        dir.write("root/FullAWSAccess.json", ALLOW_ALL);
+        dir.write("root/workloads/FullAWSAccess.json", ALLOW_ALL);
+        dir.write("root/sandbox/FullAWSAccess.json", ALLOW_ALL);
+
+        let error = load_scps(&[dir.0.join("root")]).unwrap_err();
+        assert!(error.contains("more than one child OU"), "{}", error);
+
+        let wrapper = TempDir::new("wrapper-siblings");
+        wrapper.write("a/root/FullAWSAccess.json", ALLOW_ALL);
+        wrapper.write("b/root/FullAWSAccess.json", ALLOW_ALL);
+        let error = load_scps(std::slice::from_ref(&wrapper.0)).unwrap_err();
+        assert!(error.contains("more than one subdirectory"), "{}", error);
+    }
+}
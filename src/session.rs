@@ -0,0 +1,51 @@
+use crate::error::Error;
+use crate::evaluation::NamedPolicy;
+use crate::policy::PolicyDocument;
+use crate::source::{PolicyOrigin, PolicySource, PolicyType};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Inline session policies from `paths`, then the managed policies in `arns`,
+// read through `source`. A session is only allowed what both these and its
+// principal's policies allow.
+pub async fn collect_session_policies<S: PolicySource>(
+    source: &S,
+    paths: &[PathBuf],
+    arns: &[String],
+) -> Result<Vec<(NamedPolicy, PolicyOrigin)>, Error> {
+    let mut policies = vec![];
+    for path in paths {
+        let origin = PolicyOrigin {
+            policy_type: PolicyType::Session,
+            arn: None,
+            holder: None,
+        };
+        policies.push((load_session_policy(path)?, origin));
+    }
+    for arn in arns {
+        let document = source.get_managed_policy_document(arn).await?;
+        let name = arn.rsplit('/').next().unwrap_or(arn).to_string();
+        let origin = PolicyOrigin {
+            policy_type: PolicyType::Session,
+            arn: Some(arn.clone()),
+            holder: None,
+        };
+        policies.push(((name, document), origin));
+    }
+    Ok(policies)
+}
+
+// The same plain policy document `aws sts assume-role --policy` takes.
+fn load_session_policy(path: &Path) -> Result<NamedPolicy, Error> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read session policy file {}: {}", path.display(), e))?;
+    let document: PolicyDocument = content.parse().map_err(|e: serde_json::Error| Error::PolicyParse {
+        policy: format!("session policy {}", path.display()),
+        message: e.to_string(),
+    })?;
+    let name = path
+        .file_stem()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok((name, document))
+}
@@ -0,0 +1,929 @@
+use crate::auth_details::{AuthorizationDetails, TagDetail};
+use crate::condition::RequestContext;
+use crate::error::Error;
+use crate::evaluation::NamedPolicy;
+use crate::policy::PolicyDocument;
+use aws_sdk_iam::types::AttachedPolicy;
+use aws_sdk_iam::Client as iamClient;
+use futures::future::{join_all, Future};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use urlencoding::decode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntityKind {
+    User,
+    Group,
+    Role,
+}
+
+impl Display for EntityKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            EntityKind::User => write!(f, "user"),
+            EntityKind::Group => write!(f, "group"),
+            EntityKind::Role => write!(f, "role"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Entity {
+    pub kind: EntityKind,
+    pub name: String,
+}
+
+impl Entity {
+    pub fn new(kind: EntityKind, name: impl Into<String>) -> Self {
+        Entity {
+            kind,
+            name: name.into(),
+        }
+    }
+}
+
+impl Display for Entity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} '{}'", self.kind, self.name)
+    }
+}
+
+/// A managed policy, referenced by name and ARN.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyRef {
+    pub name: String,
+    pub arn: String,
+}
+
+/// What IAM knows about an entity besides its attached and inline policies,
+/// read with a single call.
+#[derive(Debug, Default)]
+pub struct PrincipalDetails {
+    pub arn: Option<String>,
+    /// The unique ID, e.g. `AIDA...` for users and `AROA...` for roles. Groups have none.
+    pub id: Option<String>,
+    pub tags: Vec<(String, String)>,
+    pub permissions_boundary: Option<PolicyRef>,
+    /// The role's `AssumeRolePolicyDocument`, or why it could not be read.
+    /// Users and groups have none.
+    pub trust_policy: Option<Result<PolicyDocument, Error>>,
+}
+
+/// How a policy applies to the entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyType {
+    /// A managed policy attached to the entity itself.
+    Managed,
+    Inline,
+    /// A managed or inline policy of one of the user's groups.
+    Group,
+    Boundary,
+    Scp,
+    Resource,
+    /// A session policy passed when the session was created.
+    Session,
+}
+
+/// Where an identity policy comes from. Inline policies have no ARN.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyOrigin {
+    pub policy_type: PolicyType,
+    pub arn: Option<String>,
+    /// The user, group or role an inline policy is embedded in.
+    pub holder: Option<Entity>,
+}
+
+/// Where the analysis reads IAM data from: live IAM calls, or memory.
+/// Documents are returned decoded.
+pub trait PolicySource {
+    fn list_entity_names(
+        &self,
+        kind: EntityKind,
+    ) -> impl Future<Output = Result<Vec<String>, Error>> + Send;
+
+    fn list_attached_policies(
+        &self,
+        entity: &Entity,
+    ) -> impl Future<Output = Result<Vec<PolicyRef>, Error>> + Send;
+
+    fn list_inline_policies(
+        &self,
+        entity: &Entity,
+    ) -> impl Future<Output = Result<Vec<String>, Error>> + Send;
+
+    fn list_groups_for_user(
+        &self,
+        user_name: &str,
+    ) -> impl Future<Output = Result<Vec<String>, Error>> + Send;
+
+    /// Default version document of a managed policy.
+    fn get_managed_policy_document(
+        &self,
+        policy_arn: &str,
+    ) -> impl Future<Output = Result<PolicyDocument, Error>> + Send;
+
+    fn get_inline_policy_document(
+        &self,
+        entity: &Entity,
+        policy_name: &str,
+    ) -> impl Future<Output = Result<PolicyDocument, Error>> + Send;
+
+    /// Fails with `Error::EntityNotFound` when the entity does not exist.
+    fn get_principal(
+        &self,
+        entity: &Entity,
+    ) -> impl Future<Output = Result<PrincipalDetails, Error>> + Send;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EntityPolicies {
+    /// Matched against `Principal` elements of resource policies.
+    pub arn: Option<String>,
+    pub identity: Vec<NamedPolicy>,
+    /// The origin of each policy in `identity`, in the same order.
+    pub identity_origins: Vec<PolicyOrigin>,
+    pub permissions_boundary: Option<NamedPolicy>,
+    pub permissions_boundary_arn: Option<String>,
+    /// Policies passed to `AssumeRole` for the session being checked.
+    pub session_policies: Vec<NamedPolicy>,
+    /// The origin of each policy in `session_policies`, in the same order.
+    pub session_policy_origins: Vec<PolicyOrigin>,
+    /// Condition keys describing the entity, used when the request does not set them.
+    pub principal_context: RequestContext,
+    /// Who can assume the role. Users and groups have none.
+    pub trust_policy: Option<PolicyDocument>,
+    /// Policies that could not be read and are missing from the analysis.
+    pub warnings: Vec<PolicyWarning>,
+}
+
+/// Something that could not be read while collecting an entity's policies.
+/// Decisions are made without it, so a deny may be wrong.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PolicyWarning {
+    /// What is missing, e.g. "managed policy arn:aws:iam::aws:policy/ReadOnlyAccess of group 'dev'".
+    pub subject: String,
+    pub message: String,
+}
+
+impl Display for PolicyWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.subject, self.message)
+    }
+}
+
+// Turns failures to read part of an entity's policies into warnings. Only
+// the entity itself not existing is still an error.
+struct Warnings<'a> {
+    entity: &'a Entity,
+    warnings: Vec<PolicyWarning>,
+}
+
+impl Warnings<'_> {
+    fn keep<T>(&mut self, result: Result<T, Error>, subject: impl FnOnce() -> String) -> Result<Option<T>, Error> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::EntityNotFound(e)) if e == *self.entity => Err(Error::EntityNotFound(e)),
+            Err(e) => {
+                // The subject already names the policy.
+                let message = match e {
+                    Error::PolicyParse { message, .. } => message,
+                    e => e.to_string(),
+                };
+                self.warnings.push(PolicyWarning {
+                    subject: subject(),
+                    message,
+                });
+                Ok(None)
+            }
+        }
+    }
+}
+
+// Managed then inline policies of the entity, followed, for users, by the
+// managed and then inline policies of their groups. Policies that cannot be
+// read are skipped and reported in `warnings`.
+pub async fn collect_entity_policies<S: PolicySource>(
+    source: &S,
+    entity: &Entity,
+) -> Result<EntityPolicies, Error> {
+    let mut warnings = Warnings {
+        entity,
+        warnings: vec![],
+    };
+    // Checked first, so that a missing entity is an error rather than a list of warnings.
+    let principal = source.get_principal(entity).await;
+    let mut principal = warnings
+        .keep(principal, || format!("ARN, tags and permissions boundary of {}", entity))?
+        .unwrap_or_default();
+
+    let groups: Vec<Entity> = match entity.kind {
+        EntityKind::User => {
+            let groups = source.list_groups_for_user(&entity.name).await;
+            warnings
+                .keep(groups, || format!("groups of {}", entity))?
+                .unwrap_or_default()
+                .into_iter()
+                .map(|group| Entity::new(EntityKind::Group, group))
+                .collect()
+        }
+        _ => vec![],
+    };
+
+    let mut collected = collect_managed_policies(source, entity, PolicyType::Managed, &mut warnings).await?;
+    collected.append(&mut collect_inline_policies(source, entity, PolicyType::Inline, &mut warnings).await?);
+    for group in &groups {
+        collected.append(&mut collect_managed_policies(source, group, PolicyType::Group, &mut warnings).await?);
+    }
+    for group in &groups {
+        collected.append(&mut collect_inline_policies(source, group, PolicyType::Group, &mut warnings).await?);
+    }
+    let (identity, identity_origins) = collected.into_iter().unzip();
+
+    let (permissions_boundary, permissions_boundary_arn) = match principal.permissions_boundary.take() {
+        Some(boundary) => {
+            let document = source.get_managed_policy_document(&boundary.arn).await;
+            let subject = || format!("permissions boundary {} of {}", boundary.arn, entity);
+            let document = warnings.keep(document, subject)?;
+            (document.map(|d| (boundary.name, d)), Some(boundary.arn))
+        }
+        None => (None, None),
+    };
+
+    let trust_policy = match principal.trust_policy.take() {
+        Some(document) => warnings.keep(document, || format!("trust policy of {}", entity))?,
+        None => None,
+    };
+
+    Ok(EntityPolicies {
+        principal_context: principal_context(entity, &principal),
+        arn: principal.arn,
+        identity,
+        identity_origins,
+        permissions_boundary,
+        permissions_boundary_arn,
+        session_policies: vec![],
+        session_policy_origins: vec![],
+        trust_policy,
+        warnings: warnings.warnings,
+    })
+}
+
+// The keys policy variables like `${aws:username}` and `${aws:PrincipalTag/team}`
+// refer to. A role's `aws:userid` includes the session name, so it is left to the request.
+fn principal_context(entity: &Entity, details: &PrincipalDetails) -> RequestContext {
+    let mut context = RequestContext::new();
+    if entity.kind == EntityKind::Group {
+        return context;
+    }
+    if entity.kind == EntityKind::User {
+        context.insert("aws:username", entity.name.clone());
+        if let Some(id) = &details.id {
+            context.insert("aws:userid", id.clone());
+        }
+    }
+    if let Some(arn) = &details.arn {
+        context.insert("aws:PrincipalArn", arn.clone());
+    }
+    for (key, value) in &details.tags {
+        context.insert(&format!("aws:PrincipalTag/{}", key), value.clone());
+    }
+    context
+}
+
+async fn collect_managed_policies<S: PolicySource>(
+    source: &S,
+    holder: &Entity,
+    policy_type: PolicyType,
+    warnings: &mut Warnings<'_>,
+) -> Result<Vec<(NamedPolicy, PolicyOrigin)>, Error> {
+    let attached = source.list_attached_policies(holder).await;
+    let Some(attached) = warnings.keep(attached, || format!("managed policies attached to {}", holder))? else {
+        return Ok(vec![]);
+    };
+    let documents = attached
+        .iter()
+        .map(|p| source.get_managed_policy_document(&p.arn));
+    let documents = join_all(documents).await;
+
+    let mut policies = vec![];
+    for (p, document) in attached.into_iter().zip(documents) {
+        if let Some(document) = warnings.keep(document, || format!("managed policy {} of {}", p.arn, holder))? {
+            let origin = PolicyOrigin {
+                policy_type,
+                arn: Some(p.arn),
+                holder: None,
+            };
+            policies.push(((p.name, document), origin));
+        }
+    }
+    Ok(policies)
+}
+
+async fn collect_inline_policies<S: PolicySource>(
+    source: &S,
+    holder: &Entity,
+    policy_type: PolicyType,
+    warnings: &mut Warnings<'_>,
+) -> Result<Vec<(NamedPolicy, PolicyOrigin)>, Error> {
+    let names = source.list_inline_policies(holder).await;
+    let Some(names) = warnings.keep(names, || format!("inline policies of {}", holder))? else {
+        return Ok(vec![]);
+    };
+    let documents = names
+        .iter()
+        .map(|name| source.get_inline_policy_document(holder, name));
+    let documents = join_all(documents).await;
+
+    let mut policies = vec![];
+    for (name, document) in names.into_iter().zip(documents) {
+        if let Some(document) = warnings.keep(document, || format!("inline policy {} of {}", name, holder))? {
+            let origin = PolicyOrigin {
+                policy_type,
+                arn: None,
+                holder: Some(holder.clone()),
+            };
+            policies.push(((name, document), origin));
+        }
+    }
+    Ok(policies)
+}
+
+/// Decodes a URL-encoded document as IAM returns it. `policy` names it in errors.
+pub fn decode_policy_document(policy: &str, policy_document: &str) -> Result<PolicyDocument, Error> {
+    let parse_error = |message: String| Error::PolicyParse {
+        policy: policy.to_string(),
+        message,
+    };
+    let decoded = decode(policy_document).map_err(|e| parse_error(e.to_string()))?;
+    decoded.parse().map_err(|e: serde_json::Error| parse_error(e.to_string()))
+}
+
+/// Reads policies through the IAM API.
+#[derive(Debug, Clone)]
+pub struct SdkPolicySource {
+    client: iamClient,
+}
+
+impl SdkPolicySource {
+    pub fn new(client: iamClient) -> Self {
+        SdkPolicySource { client }
+    }
+}
+
+impl PolicySource for SdkPolicySource {
+    async fn list_entity_names(&self, kind: EntityKind) -> Result<Vec<String>, Error> {
+        let names = match kind {
+            EntityKind::User => crate::list_users(&self.client, None)
+                .await?
+                .into_iter()
+                .map(|u| u.user_name)
+                .collect(),
+            EntityKind::Group => crate::list_groups(&self.client, None)
+                .await?
+                .into_iter()
+                .map(|g| g.group_name)
+                .collect(),
+            EntityKind::Role => crate::list_roles(&self.client, None)
+                .await?
+                .into_iter()
+                .map(|r| r.role_name)
+                .collect(),
+        };
+        Ok(names)
+    }
+
+    async fn list_attached_policies(&self, entity: &Entity) -> Result<Vec<PolicyRef>, Error> {
+        let attached = match entity.kind {
+            EntityKind::User => crate::list_attached_user_policies(&self.client, &entity.name).await?,
+            EntityKind::Group => crate::list_attached_group_policies(&self.client, &entity.name).await?,
+            EntityKind::Role => crate::list_attached_role_policies(&self.client, &entity.name).await?,
+        };
+        Ok(attached
+            .into_iter()
+            .map(|a_p| PolicyRef {
+                name: a_p.policy_name.unwrap_or_default(),
+                arn: a_p.policy_arn.unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    async fn list_inline_policies(&self, entity: &Entity) -> Result<Vec<String>, Error> {
+        let names = match entity.kind {
+            EntityKind::User => crate::list_user_policies(&self.client, &entity.name).await?,
+            EntityKind::Group => crate::list_group_policies(&self.client, &entity.name).await?,
+            EntityKind::Role => crate::list_role_policies(&self.client, &entity.name).await?,
+        };
+        Ok(names)
+    }
+
+    async fn list_groups_for_user(&self, user_name: &str) -> Result<Vec<String>, Error> {
+        crate::list_groups_for_user(&self.client, user_name).await
+    }
+
+    async fn get_managed_policy_document(&self, policy_arn: &str) -> Result<PolicyDocument, Error> {
+        let attached = AttachedPolicy::builder().policy_arn(policy_arn).build();
+        let policy = crate::get_policy(&self.client, attached).await?;
+        let document = crate::get_policy_version(&self.client, policy)
+            .await?
+            .document
+            .ok_or_else(|| Error::PolicyNotFound(policy_arn.to_string()))?;
+        decode_policy_document(policy_arn, &document)
+    }
+
+    async fn get_inline_policy_document(
+        &self,
+        entity: &Entity,
+        policy_name: &str,
+    ) -> Result<PolicyDocument, Error> {
+        let (name, policy_name) = (&entity.name, &policy_name.to_string());
+        let document = match entity.kind {
+            EntityKind::User => crate::get_user_policy(&self.client, name, policy_name).await?,
+            EntityKind::Group => crate::get_group_policy(&self.client, name, policy_name).await?,
+            EntityKind::Role => crate::get_role_policy(&self.client, name, policy_name).await?,
+        };
+        decode_policy_document(policy_name, &document)
+    }
+
+    async fn get_principal(&self, entity: &Entity) -> Result<PrincipalDetails, Error> {
+        let tags = |tags: &[aws_sdk_iam::types::Tag]| {
+            tags.iter()
+                .map(|t| (t.key.clone(), t.value.clone()))
+                .collect()
+        };
+        // The name of a managed policy is the last part of its ARN.
+        let boundary = |boundary: Option<aws_sdk_iam::types::AttachedPermissionsBoundary>| {
+            boundary.and_then(|b| b.permissions_boundary_arn).map(|arn| PolicyRef {
+                name: arn.rsplit('/').next().unwrap_or(&arn).to_string(),
+                arn,
+            })
+        };
+        let details = match entity.kind {
+            EntityKind::User => crate::get_user(&self.client, entity.name.clone())
+                .await?
+                .user
+                .map(|u| PrincipalDetails {
+                    tags: tags(u.tags()),
+                    permissions_boundary: boundary(u.permissions_boundary),
+                    arn: Some(u.arn),
+                    id: Some(u.user_id),
+                    trust_policy: None,
+                }),
+            EntityKind::Group => crate::get_group(&self.client, entity.name.clone())
+                .await?
+                .group
+                .map(|g| PrincipalDetails {
+                    arn: Some(g.arn),
+                    ..Default::default()
+                }),
+            EntityKind::Role => crate::get_role(&self.client, entity.name.clone())
+                .await?
+                .role
+                .map(|r| {
+                    let policy = format!("trust policy of {}", entity);
+                    PrincipalDetails {
+                        tags: tags(r.tags()),
+                        permissions_boundary: boundary(r.permissions_boundary),
+                        trust_policy: r
+                            .assume_role_policy_document
+                            .map(|d| decode_policy_document(&policy, &d)),
+                        arn: Some(r.arn),
+                        id: Some(r.role_id),
+                    }
+                }),
+        };
+        Ok(details.unwrap_or_default())
+    }
+}
+
+/// Serves policies from memory, e.g. an authorization details snapshot or test fixtures.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryPolicySource {
+    managed_policies: HashMap<String, (String, StoredDocument)>,
+    entities: HashMap<Entity, EntityRecord>,
+    entity_names: HashMap<EntityKind, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct EntityRecord {
+    attached: Vec<String>,
+    inline: Vec<(String, StoredDocument)>,
+    groups: Vec<String>,
+    permissions_boundary: Option<String>,
+    arn: Option<String>,
+    id: Option<String>,
+    tags: Vec<(String, String)>,
+    trust_policy: Option<StoredDocument>,
+}
+
+// A document, or why it could not be parsed.
+type StoredDocument = Result<PolicyDocument, String>;
+
+impl InMemoryPolicySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_entity(&mut self, entity: &Entity) {
+        if !self.entities.contains_key(entity) {
+            self.entity_names
+                .entry(entity.kind)
+                .or_default()
+                .push(entity.name.clone());
+        }
+        self.entities.entry(entity.clone()).or_default();
+    }
+
+    pub fn add_managed_policy(&mut self, arn: &str, name: &str, document: PolicyDocument) {
+        self.managed_policies
+            .insert(arn.to_string(), (name.to_string(), Ok(document)));
+    }
+
+    /// A managed policy whose document could not be parsed. Reading it fails with `message`.
+    pub fn add_unreadable_managed_policy(&mut self, arn: &str, name: &str, message: &str) {
+        self.managed_policies
+            .insert(arn.to_string(), (name.to_string(), Err(message.to_string())));
+    }
+
+    pub fn attach_managed_policy(&mut self, entity: &Entity, arn: &str) {
+        self.record(entity).attached.push(arn.to_string());
+    }
+
+    pub fn put_inline_policy(&mut self, entity: &Entity, name: &str, document: PolicyDocument) {
+        self.record(entity).inline.push((name.to_string(), Ok(document)));
+    }
+
+    /// An inline policy whose document could not be parsed. Reading it fails with `message`.
+    pub fn put_unreadable_inline_policy(&mut self, entity: &Entity, name: &str, message: &str) {
+        self.record(entity)
+            .inline
+            .push((name.to_string(), Err(message.to_string())));
+    }
+
+    pub fn add_user_to_group(&mut self, user_name: &str, group_name: &str) {
+        let user = Entity::new(EntityKind::User, user_name);
+        self.record(&user).groups.push(group_name.to_string());
+    }
+
+    pub fn set_permissions_boundary(&mut self, entity: &Entity, arn: &str) {
+        self.record(entity).permissions_boundary = Some(arn.to_string());
+    }
+
+    pub fn set_entity_arn(&mut self, entity: &Entity, arn: &str) {
+        self.record(entity).arn = Some(arn.to_string());
+    }
+
+    pub fn set_principal_id(&mut self, entity: &Entity, id: &str) {
+        self.record(entity).id = Some(id.to_string());
+    }
+
+    pub fn add_principal_tag(&mut self, entity: &Entity, key: &str, value: &str) {
+        self.record(entity).tags.push((key.to_string(), value.to_string()));
+    }
+
+    pub fn set_trust_policy(&mut self, entity: &Entity, document: PolicyDocument) {
+        self.record(entity).trust_policy = Some(Ok(document));
+    }
+
+    fn set_principal_details(&mut self, entity: &Entity, id: &str, tags: &[TagDetail]) {
+        if !id.is_empty() {
+            self.set_principal_id(entity, id);
+        }
+        for tag in tags {
+            self.add_principal_tag(entity, &tag.key, &tag.value);
+        }
+    }
+
+    fn put_parsed_inline_policy(&mut self, entity: &Entity, name: &str, document: StoredDocument) {
+        self.record(entity).inline.push((name.to_string(), document));
+    }
+
+    fn record(&mut self, entity: &Entity) -> &mut EntityRecord {
+        self.add_entity(entity);
+        self.entities.get_mut(entity).expect("entity was just added")
+    }
+
+    fn find(&self, entity: &Entity) -> Result<&EntityRecord, Error> {
+        self.entities
+            .get(entity)
+            .ok_or_else(|| Error::EntityNotFound(entity.clone()))
+    }
+
+    fn policy_name(&self, arn: &str) -> String {
+        match self.managed_policies.get(arn) {
+            Some((name, _)) => name.clone(),
+            None => arn.rsplit('/').next().unwrap_or(arn).to_string(),
+        }
+    }
+}
+
+impl From<&AuthorizationDetails> for InMemoryPolicySource {
+    fn from(details: &AuthorizationDetails) -> Self {
+        let mut source = InMemoryPolicySource::new();
+
+        for policy in &details.policies {
+            match details.managed_policy_document(&policy.arn) {
+                Some(Ok(document)) => source.add_managed_policy(&policy.arn, &policy.policy_name, document),
+                Some(Err(message)) => source.add_unreadable_managed_policy(&policy.arn, &policy.policy_name, &message),
+                None => {}
+            }
+        }
+
+        for user in &details.user_detail_list {
+            let entity = Entity::new(EntityKind::User, &user.user_name);
+            source.add_entity(&entity);
+            source.set_entity_arn(&entity, &user.arn);
+            source.set_principal_details(&entity, &user.user_id, &user.tags);
+            for a_p in &user.attached_managed_policies {
+                source.attach_managed_policy(&entity, &a_p.policy_arn);
+            }
+            for (name, document) in AuthorizationDetails::inline_policies(&user.user_policy_list) {
+                source.put_parsed_inline_policy(&entity, &name, document);
+            }
+            for group in &user.group_list {
+                source.add_user_to_group(&user.user_name, group);
+            }
+            if let Some(boundary) = &user.permissions_boundary {
+                source.set_permissions_boundary(&entity, &boundary.permissions_boundary_arn);
+            }
+        }
+
+        for group in &details.group_detail_list {
+            let entity = Entity::new(EntityKind::Group, &group.group_name);
+            source.add_entity(&entity);
+            source.set_entity_arn(&entity, &group.arn);
+            for a_p in &group.attached_managed_policies {
+                source.attach_managed_policy(&entity, &a_p.policy_arn);
+            }
+            for (name, document) in AuthorizationDetails::inline_policies(&group.group_policy_list) {
+                source.put_parsed_inline_policy(&entity, &name, document);
+            }
+        }
+
+        for role in &details.role_detail_list {
+            let entity = Entity::new(EntityKind::Role, &role.role_name);
+            source.add_entity(&entity);
+            source.set_entity_arn(&entity, &role.arn);
+            source.set_principal_details(&entity, &role.role_id, &role.tags);
+            for a_p in &role.attached_managed_policies {
+                source.attach_managed_policy(&entity, &a_p.policy_arn);
+            }
+            for (name, document) in AuthorizationDetails::inline_policies(&role.role_policy_list) {
+                source.put_parsed_inline_policy(&entity, &name, document);
+            }
+            if let Some(boundary) = &role.permissions_boundary {
+                source.set_permissions_boundary(&entity, &boundary.permissions_boundary_arn);
+            }
+            if let Some(document) = AuthorizationDetails::trust_policy(role) {
+                source.record(&entity).trust_policy = Some(document);
+            }
+        }
+
+        source
+    }
+}
+
+impl PolicySource for InMemoryPolicySource {
+    async fn list_entity_names(&self, kind: EntityKind) -> Result<Vec<String>, Error> {
+        Ok(self.entity_names.get(&kind).cloned().unwrap_or_default())
+    }
+
+    async fn list_attached_policies(&self, entity: &Entity) -> Result<Vec<PolicyRef>, Error> {
+        Ok(self
+            .find(entity)?
+            .attached
+            .iter()
+            .map(|arn| PolicyRef {
+                name: self.policy_name(arn),
+                arn: arn.clone(),
+            })
+            .collect())
+    }
+
+    async fn list_inline_policies(&self, entity: &Entity) -> Result<Vec<String>, Error> {
+        Ok(self
+            .find(entity)?
+            .inline
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect())
+    }
+
+    async fn list_groups_for_user(&self, user_name: &str) -> Result<Vec<String>, Error> {
+        let user = Entity::new(EntityKind::User, user_name);
+        Ok(self.find(&user)?.groups.clone())
+    }
+
+    async fn get_managed_policy_document(&self, policy_arn: &str) -> Result<PolicyDocument, Error> {
+        let (_, document) = self
+            .managed_policies
+            .get(policy_arn)
+            .ok_or_else(|| Error::PolicyNotFound(policy_arn.to_string()))?;
+        stored_document(policy_arn, document)
+    }
+
+    async fn get_inline_policy_document(
+        &self,
+        entity: &Entity,
+        policy_name: &str,
+    ) -> Result<PolicyDocument, Error> {
+        let policy = format!("{} of {}", policy_name, entity);
+        let (_, document) = self
+            .find(entity)?
+            .inline
+            .iter()
+            .find(|(name, _)| name == policy_name)
+            .ok_or_else(|| Error::PolicyNotFound(policy.clone()))?;
+        stored_document(&policy, document)
+    }
+
+    async fn get_principal(&self, entity: &Entity) -> Result<PrincipalDetails, Error> {
+        let record = self.find(entity)?;
+        let policy = format!("trust policy of {}", entity);
+        Ok(PrincipalDetails {
+            arn: record.arn.clone(),
+            id: record.id.clone(),
+            tags: record.tags.clone(),
+            permissions_boundary: record.permissions_boundary.as_ref().map(|arn| PolicyRef {
+                name: self.policy_name(arn),
+                arn: arn.clone(),
+            }),
+            trust_policy: record
+                .trust_policy
+                .as_ref()
+                .map(|document| stored_document(&policy, document)),
+        })
+    }
+}
+
+fn stored_document(policy: &str, document: &StoredDocument) -> Result<PolicyDocument, Error> {
+    document.clone().map_err(|message| Error::PolicyParse {
+        policy: policy.to_string(),
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluation::{evaluate_entity, Decision, LayeredEvaluation, PolicyLayer};
+    use crate::policy::Principal;
+    use crate::test_util::{policy, request};
+    use std::collections::BTreeMap;
+
+    async fn evaluate(source: &InMemoryPolicySource, entity: &Entity, action_name: &str) -> LayeredEvaluation {
+        let policies = collect_entity_policies(source, entity).await.expect("entity exists");
+        evaluate_entity(&policies, &[], None, &request(action_name, None))
+    }
+
+    async fn decide(source: &InMemoryPolicySource, entity: &Entity, action_name: &str) -> Decision {
+        evaluate(source, entity, action_name).await.decision
+    }
+
+    #[tokio::test]
+    async fn explicit_deny_wins_over_allow() {
+        let alice = Entity::new(EntityKind::User, "alice");
+        let mut source = InMemoryPolicySource::new();
+        source.add_managed_policy(
+            "arn:aws:iam::aws:policy/AmazonEC2FullAccess",
+            "AmazonEC2FullAccess",
+            policy(r#"[{"Effect":"Allow","Action":"ec2:*","Resource":"*"}]"#),
+        );
+        source.attach_managed_policy(&alice, "arn:aws:iam::aws:policy/AmazonEC2FullAccess");
+        source.put_inline_policy(
+            &alice,
+            "DenyTerminate",
+            policy(r#"[{"Effect":"Deny","Action":"ec2:TerminateInstances","Resource":"*"}]"#),
+        );
+
+        assert_eq!(decide(&source, &alice, "ec2:RunInstances").await, Decision::Allowed);
+        assert_eq!(decide(&source, &alice, "ec2:TerminateInstances").await, Decision::ExplicitDeny);
+    }
+
+    #[tokio::test]
+    async fn permissions_boundary_caps_identity_policies() {
+        let deployer = Entity::new(EntityKind::Role, "deployer");
+        let mut source = InMemoryPolicySource::new();
+        source.put_inline_policy(&deployer, "admin", policy(r#"[{"Effect":"Allow","Action":"*","Resource":"*"}]"#));
+        source.add_managed_policy(
+            "arn:aws:iam::111122223333:policy/Boundary",
+            "Boundary",
+            policy(r#"[{"Effect":"Allow","Action":"s3:*","Resource":"*"}]"#),
+        );
+        source.set_permissions_boundary(&deployer, "arn:aws:iam::111122223333:policy/Boundary");
+
+        assert_eq!(decide(&source, &deployer, "s3:GetObject").await, Decision::Allowed);
+        let evaluation = evaluate(&source, &deployer, "iam:CreateUser").await;
+        assert_eq!(evaluation.decision, Decision::ImplicitDeny);
+        assert_eq!(
+            evaluation.blocking_layer().map(|l| l.layer),
+            Some(PolicyLayer::PermissionsBoundary)
+        );
+    }
+
+    #[tokio::test]
+    async fn group_inline_policies_apply_to_members() {
+        let alice = Entity::new(EntityKind::User, "alice");
+        let devs = Entity::new(EntityKind::Group, "devs");
+        let mut source = InMemoryPolicySource::new();
+        source.add_entity(&alice);
+        source.put_inline_policy(&devs, "logs", policy(r#"[{"Effect":"Allow","Action":"logs:*","Resource":"*"}]"#));
+        source.add_user_to_group("alice", "devs");
+
+        let policies = collect_entity_policies(&source, &alice).await.expect("entity exists");
+        assert_eq!(policies.identity.len(), 1);
+        assert_eq!(decide(&source, &alice, "logs:PutLogEvents").await, Decision::Allowed);
+        assert_eq!(decide(&source, &alice, "s3:GetObject").await, Decision::ImplicitDeny);
+    }
+
+    #[tokio::test]
+    async fn unreadable_policies_become_warnings() {
+        let alice = Entity::new(EntityKind::User, "alice");
+        let mut source = InMemoryPolicySource::new();
+        source.put_inline_policy(&alice, "s3", policy(r#"[{"Effect":"Allow","Action":"s3:*","Resource":"*"}]"#));
+        source.put_unreadable_inline_policy(&alice, "broken", "expected value at line 1 column 1");
+        source.add_unreadable_managed_policy("arn:aws:iam::111122223333:policy/Legacy", "Legacy", "unknown field");
+        source.attach_managed_policy(&alice, "arn:aws:iam::111122223333:policy/Legacy");
+
+        let policies = collect_entity_policies(&source, &alice).await.expect("entity exists");
+        let subjects: Vec<&str> = policies.warnings.iter().map(|w| w.subject.as_str()).collect();
+        assert_eq!(
+            subjects,
+            [
+                "managed policy arn:aws:iam::111122223333:policy/Legacy of user 'alice'",
+                "inline policy broken of user 'alice'",
+            ]
+        );
+        assert_eq!(policies.warnings[1].message, "expected value at line 1 column 1");
+        assert_eq!(decide(&source, &alice, "s3:GetObject").await, Decision::Allowed);
+    }
+
+    #[tokio::test]
+    async fn missing_entity_is_still_an_error() {
+        let source = InMemoryPolicySource::new();
+        let bob = Entity::new(EntityKind::User, "bob");
+        let result = collect_entity_policies(&source, &bob).await;
+        assert!(matches!(result, Err(Error::EntityNotFound(e)) if e == bob));
+    }
+
+    fn snapshot() -> InMemoryPolicySource {
+        let json = include_str!("../tests/fixtures/authorization-details.json");
+        let details = AuthorizationDetails::from_json(json).expect("fixture should parse");
+        InMemoryPolicySource::from(&details)
+    }
+
+    fn actions(document: &PolicyDocument) -> Vec<String> {
+        document.statement.iter().flat_map(|s| s.action.clone().unwrap_or_default()).collect()
+    }
+
+    #[tokio::test]
+    async fn snapshot_holds_entities_and_their_details() {
+        let source = snapshot();
+        let alice = Entity::new(EntityKind::User, "alice");
+
+        assert_eq!(source.list_entity_names(EntityKind::Role).await.unwrap(), ["app"]);
+        assert_eq!(source.list_groups_for_user("alice").await.unwrap(), ["developers"]);
+        let attached = source.list_attached_policies(&alice).await.unwrap();
+        assert_eq!(attached[0].name, "ReadObjects");
+
+        let principal = source.get_principal(&alice).await.unwrap();
+        assert_eq!(principal.arn.as_deref(), Some("arn:aws:iam::123456789012:user/alice"));
+        assert_eq!(principal.id.as_deref(), Some("AIDAEXAMPLEALICE"));
+        assert_eq!(principal.tags, [("team".to_string(), "data".to_string())]);
+        assert_eq!(principal.permissions_boundary.map(|b| b.name).as_deref(), Some("Boundary"));
+    }
+
+    #[tokio::test]
+    async fn snapshot_documents_are_url_encoded_strings_or_objects() {
+        let source = snapshot();
+        let alice = Entity::new(EntityKind::User, "alice");
+        let developers = Entity::new(EntityKind::Group, "developers");
+        let app = Entity::new(EntityKind::Role, "app");
+
+        let encoded = source.get_inline_policy_document(&alice, "DenyTerminate").await.unwrap();
+        assert_eq!(actions(&encoded), ["ec2:TerminateInstances"]);
+        let object = source.get_inline_policy_document(&developers, "AllowLogs").await.unwrap();
+        assert_eq!(actions(&object), ["logs:*"]);
+
+        let trust_policy = source.get_principal(&app).await.unwrap().trust_policy;
+        let trust_policy = trust_policy.expect("roles have a trust policy").unwrap();
+        let services = BTreeMap::from([("Service".to_string(), vec!["lambda.amazonaws.com".to_string()])]);
+        assert_eq!(trust_policy.statement[0].principal, Some(Principal::Typed(services)));
+    }
+
+    #[tokio::test]
+    async fn snapshot_managed_policies_use_their_default_version() {
+        let source = snapshot();
+        let alice = Entity::new(EntityKind::User, "alice");
+
+        let document =
+            source.get_managed_policy_document("arn:aws:iam::123456789012:policy/ReadObjects").await.unwrap();
+        assert_eq!(actions(&document), ["s3:GetObject"]);
+        assert_eq!(decide(&source, &alice, "s3:GetObject").await, Decision::Allowed);
+        assert_eq!(decide(&source, &alice, "s3:PutObject").await, Decision::ImplicitDeny);
+        assert_eq!(decide(&source, &alice, "logs:PutLogEvents").await, Decision::Allowed);
+        assert_eq!(decide(&source, &alice, "ec2:TerminateInstances").await, Decision::ExplicitDeny);
+    }
+}
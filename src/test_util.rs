@@ -0,0 +1,20 @@
+//! Factories shared by the unit tests.
+
+use crate::condition::RequestContext;
+use crate::evaluation::Request;
+use crate::policy::PolicyDocument;
+
+/// A policy document holding `statements`, a JSON array of statements.
+pub fn policy(statements: &str) -> PolicyDocument {
+    format!(r#"{{"Version":"2012-10-17","Statement":{}}}"#, statements)
+        .parse()
+        .expect("test policy should parse")
+}
+
+pub fn request(action_name: &str, resource_arn: Option<&str>) -> Request {
+    Request {
+        action_name: action_name.to_string(),
+        resource_arn: resource_arn.map(|arn| arn.to_string()),
+        context: RequestContext::new(),
+    }
+}
@@ -0,0 +1,168 @@
+use crate::evaluation::check_action_in_statement;
+use crate::policy::{Effect, PolicyDocument, Principal, Statement};
+use serde::Serialize;
+use std::fmt::Display;
+
+/// The ways a role session is created.
+pub const ASSUME_ACTIONS: [&str; 3] = [
+    "sts:AssumeRole",
+    "sts:AssumeRoleWithWebIdentity",
+    "sts:AssumeRoleWithSAML",
+];
+
+/// One principal named in a trust policy statement.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TrustedPrincipal {
+    /// `AWS`, `Service`, `Federated`, `CanonicalUser`, or `*` for `"Principal": "*"`.
+    pub kind: String,
+    pub value: String,
+    /// Named through `NotPrincipal`, so the statement covers everyone else.
+    pub negated: bool,
+}
+
+impl Display for TrustedPrincipal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let prefix = if self.negated { "everyone except " } else { "" };
+        match self.kind.as_str() {
+            "*" => write!(f, "{}anyone (*)", prefix),
+            "AWS" if self.value == "*" => write!(f, "{}any AWS principal (*)", prefix),
+            "AWS" => match account_root(&self.value) {
+                Some(account) => write!(
+                    f,
+                    "{}account {}, for principals its own policies allow",
+                    prefix, account
+                ),
+                None => write!(f, "{}{}", prefix, self.value),
+            },
+            "Service" => write!(f, "{}service {}", prefix, self.value),
+            "Federated" => write!(f, "{}federated {}", prefix, self.value),
+            kind => write!(f, "{}{} {}", prefix, kind, self.value),
+        }
+    }
+}
+
+// `arn:aws:iam::123456789012:root` or a bare account ID.
+fn account_root(value: &str) -> Option<&str> {
+    if value.len() == 12 && value.chars().all(|c| c.is_ascii_digit()) {
+        return Some(value);
+    }
+    let account = value.strip_prefix("arn:")?.split(':').nth(3)?;
+    value.ends_with(":root").then_some(account)
+}
+
+/// A condition attached to a trust statement, labeled when it is a key that
+/// commonly guards role assumption.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TrustCondition {
+    pub operator: String,
+    pub key: String,
+    pub values: Vec<String>,
+}
+
+impl TrustCondition {
+    pub fn label(&self) -> Option<&'static str> {
+        let key = self.key.to_lowercase();
+        match key.as_str() {
+            "sts:externalid" => Some("external ID"),
+            "aws:sourceaccount" => Some("source account"),
+            "aws:sourcearn" => Some("source ARN"),
+            "aws:principalorgid" => Some("organization"),
+            "saml:aud" => Some("SAML audience"),
+            _ if key.ends_with(":sub") => Some("OIDC subject"),
+            _ if key.ends_with(":aud") || key.ends_with(":azp") => Some("OIDC audience"),
+            _ => None,
+        }
+    }
+}
+
+impl Display for TrustCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let values = self
+            .values
+            .iter()
+            .map(|v| format!("\"{}\"", v))
+            .collect::<Vec<String>>()
+            .join(" or ");
+        match self.label() {
+            Some(label) => write!(f, "{} ({}) {} {}", label, self.key, self.operator, values),
+            None => write!(f, "{} {} {}", self.key, self.operator, values),
+        }
+    }
+}
+
+/// What one trust policy statement allows or denies.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrustGrant {
+    pub sid: Option<String>,
+    pub effect: Effect,
+    pub principals: Vec<TrustedPrincipal>,
+    /// The members of `ASSUME_ACTIONS` the statement covers.
+    pub actions: Vec<String>,
+    pub conditions: Vec<TrustCondition>,
+}
+
+// Statements that cover none of the assume actions, e.g. only
+// `sts:TagSession`, are left out.
+pub fn analyze_trust_policy(document: &PolicyDocument) -> Vec<TrustGrant> {
+    document
+        .statement
+        .iter()
+        .filter_map(|statement| {
+            let actions: Vec<String> = ASSUME_ACTIONS
+                .iter()
+                .filter(|action| check_action_in_statement(statement, action))
+                .map(|action| action.to_string())
+                .collect();
+            if actions.is_empty() {
+                return None;
+            }
+            Some(TrustGrant {
+                sid: statement.sid.clone(),
+                effect: statement.effect,
+                principals: trusted_principals(statement),
+                actions,
+                conditions: trust_conditions(statement),
+            })
+        })
+        .collect()
+}
+
+fn trusted_principals(statement: &Statement) -> Vec<TrustedPrincipal> {
+    let (principal, negated) = match (&statement.principal, &statement.not_principal) {
+        (Some(principal), _) => (principal, false),
+        (None, Some(not_principal)) => (not_principal, true),
+        (None, None) => return vec![],
+    };
+    match principal {
+        Principal::Any => vec![TrustedPrincipal {
+            kind: "*".to_string(),
+            value: "*".to_string(),
+            negated,
+        }],
+        Principal::Typed(types) => types
+            .iter()
+            .flat_map(|(kind, values)| {
+                values.iter().map(move |value| TrustedPrincipal {
+                    kind: kind.clone(),
+                    value: value.clone(),
+                    negated,
+                })
+            })
+            .collect(),
+    }
+}
+
+fn trust_conditions(statement: &Statement) -> Vec<TrustCondition> {
+    statement
+        .condition
+        .iter()
+        .flatten()
+        .flat_map(|(operator, keys)| {
+            keys.iter().map(move |(key, values)| TrustCondition {
+                operator: operator.clone(),
+                key: key.clone(),
+                values: values.clone(),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,122 @@
+use crate::condition::RequestContext;
+
+/// Characters of a pattern after its policy variables are resolved. Those
+/// flagged `true` are literal and never act as wildcards: the ones written
+/// with the `${*}`, `${?}` and `${$}` escapes, and those of substituted values.
+pub type ResolvedPattern = Vec<(char, bool)>;
+
+pub fn has_variables(text: &str) -> bool {
+    text.contains("${")
+}
+
+/// Replaces every `${key}` or `${key, 'default'}` in `text` with the key's
+/// value in `context`, or its default. A key with several values cannot be
+/// substituted. Fails with the keys that could not be resolved.
+pub fn resolve(text: &str, context: &RequestContext) -> Result<ResolvedPattern, Vec<String>> {
+    let mut resolved = vec![];
+    let mut unresolved = vec![];
+    let mut rest = text;
+
+    while let Some(start) = rest.find("${") {
+        // An unterminated `${` is kept as written.
+        let Some(length) = rest[start..].find('}') else {
+            break;
+        };
+        resolved.extend(rest[..start].chars().map(|c| (c, false)));
+        let variable = &rest[start + 2..start + length];
+        rest = &rest[start + length + 1..];
+
+        if let "*" | "?" | "$" = variable {
+            resolved.extend(variable.chars().map(|c| (c, true)));
+            continue;
+        }
+        let (key, default) = parse_variable(variable);
+        match (context.get(key), default) {
+            (Some([value]), _) => resolved.extend(value.chars().map(|c| (c, true))),
+            (_, Some(default)) => resolved.extend(default.chars().map(|c| (c, true))),
+            _ => unresolved.push(key.to_string()),
+        }
+    }
+    resolved.extend(rest.chars().map(|c| (c, false)));
+
+    if unresolved.is_empty() {
+        Ok(resolved)
+    } else {
+        Err(unresolved)
+    }
+}
+
+/// The resolved text, for comparisons that have no wildcards.
+pub fn resolved_text(resolved: &[(char, bool)]) -> String {
+    resolved.iter().map(|(c, _)| c).collect()
+}
+
+// `key` or `key, 'default'`.
+fn parse_variable(variable: &str) -> (&str, Option<&str>) {
+    match variable.split_once(',') {
+        Some((key, default)) => {
+            let default = default.trim();
+            let default = default.strip_prefix('\'').and_then(|d| d.strip_suffix('\''));
+            (key.trim(), default)
+        }
+        None => (variable.trim(), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(pairs: &[&str]) -> RequestContext {
+        let pairs: Vec<String> = pairs.iter().map(|p| p.to_string()).collect();
+        RequestContext::from_pairs(&pairs).unwrap()
+    }
+
+    // The resolved text, with literal characters in brackets.
+    fn render(resolved: &[(char, bool)]) -> String {
+        resolved
+            .iter()
+            .map(|(c, literal)| if *literal { format!("[{}]", c) } else { c.to_string() })
+            .collect()
+    }
+
+    #[test]
+    fn escapes_are_literal_characters() {
+        let resolved = resolve("a${*}b${?}c${$}*", &RequestContext::new()).unwrap();
+        assert_eq!(resolved_text(&resolved), "a*b?c$*");
+        assert_eq!(render(&resolved), "a[*]b[?]c[$]*");
+    }
+
+    #[test]
+    fn keys_resolve_to_literal_values() {
+        let resolved = resolve("home/${aws:username}/*", &context(&["aws:username=al*ce"])).unwrap();
+        assert_eq!(render(&resolved), "home/[a][l][*][c][e]/*");
+    }
+
+    #[test]
+    fn defaults_apply_when_a_key_is_missing() {
+        let text = "${aws:PrincipalTag/team, 'none'}/${aws:username , 'x'}";
+        assert_eq!(resolved_text(&resolve(text, &RequestContext::new()).unwrap()), "none/x");
+        let set = context(&["aws:principaltag/team=dev"]);
+        assert_eq!(resolved_text(&resolve(text, &set).unwrap()), "dev/x");
+    }
+
+    #[test]
+    fn unresolvable_keys_are_reported() {
+        let missing = resolve("${aws:username}/${aws:userid}", &RequestContext::new());
+        assert_eq!(missing, Err(vec!["aws:username".to_string(), "aws:userid".to_string()]));
+
+        // A multivalued key cannot be substituted.
+        let multivalued = context(&["aws:TagKeys=a", "aws:TagKeys=b"]);
+        assert_eq!(
+            resolve("${aws:TagKeys}", &multivalued),
+            Err(vec!["aws:TagKeys".to_string()])
+        );
+    }
+
+    #[test]
+    fn unterminated_variables_are_kept_as_written() {
+        let resolved = resolve("a/${aws:username", &RequestContext::new()).unwrap();
+        assert_eq!(render(&resolved), "a/${aws:username");
+    }
+}
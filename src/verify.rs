@@ -0,0 +1,451 @@
+use crate::condition::RequestContext;
+use crate::error::Error;
+use crate::evaluation::{Decision, LayeredEvaluation, MatchedStatement, PolicyLayer, Request};
+use crate::resource_policy::ResourcePolicy;
+use crate::source::{Entity, EntityPolicies};
+use aws_sdk_iam::types::{
+    ContextEntry, ContextKeyTypeEnum, EvaluationResult, PolicyEvaluationDecisionType, PolicySourceType,
+};
+use aws_sdk_iam::Client as iamClient;
+use futures::Future;
+use serde::Serialize;
+use std::net::IpAddr;
+
+/// Stands for the statements of a resource policy given to the simulator,
+/// which has no name of its own.
+pub const RESOURCE_POLICY_ID: &str = "ResourcePolicy";
+
+/// What the IAM policy simulator decided for one action.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SimulatedDecision {
+    pub action: String,
+    /// Allowed, ExplicitDeny or ImplicitDeny. The simulator never returns Conditional.
+    pub decision: Decision,
+    /// The `SourcePolicyId` of each statement behind the decision.
+    pub matched_policies: Vec<String>,
+    /// Condition keys the simulator needed but was not given.
+    pub missing_context_keys: Vec<String>,
+    /// Whether the account's SCPs, which the simulator always applies, blocked the action.
+    pub denied_by_organizations: bool,
+    pub denied_by_permissions_boundary: bool,
+}
+
+/// Runs requests through AWS's own evaluator, to cross-check ours.
+pub trait PolicySimulator {
+    /// One result per action, for the resource and context of `request`.
+    fn simulate(
+        &self,
+        principal_arn: &str,
+        action_names: &[String],
+        request: &Request,
+        resource_policy: Option<&ResourcePolicy>,
+    ) -> impl Future<Output = Result<Vec<SimulatedDecision>, Error>> + Send;
+}
+
+/// Calls `iam:SimulatePrincipalPolicy`.
+#[derive(Debug, Clone)]
+pub struct SdkPolicySimulator {
+    client: iamClient,
+}
+
+impl SdkPolicySimulator {
+    pub fn new(client: iamClient) -> Self {
+        SdkPolicySimulator { client }
+    }
+}
+
+impl PolicySimulator for SdkPolicySimulator {
+    async fn simulate(
+        &self,
+        principal_arn: &str,
+        action_names: &[String],
+        request: &Request,
+        resource_policy: Option<&ResourcePolicy>,
+    ) -> Result<Vec<SimulatedDecision>, Error> {
+        let resource_policy_json = resource_policy
+            .map(|p| serde_json::to_string(&p.document))
+            .transpose()
+            .map_err(|e| Error::Other(format!("Could not serialize the resource policy: {}", e)))?;
+        let resource_owner = resource_policy
+            .and_then(|p| p.account.as_ref())
+            .map(|account| format!("arn:aws:iam::{}:root", account));
+
+        let results = crate::simulate_principal_policy(
+            &self.client,
+            principal_arn,
+            action_names.to_vec(),
+            request.resource_arn.clone().map(|arn| vec![arn]),
+            context_entries(&request.context),
+            resource_policy_json,
+            resource_owner,
+        )
+        .await?;
+        Ok(results.iter().map(simulated_decision).collect())
+    }
+}
+
+fn simulated_decision(result: &EvaluationResult) -> SimulatedDecision {
+    let decision = match result.eval_decision() {
+        PolicyEvaluationDecisionType::Allowed => Decision::Allowed,
+        PolicyEvaluationDecisionType::ExplicitDeny => Decision::ExplicitDeny,
+        _ => Decision::ImplicitDeny,
+    };
+    let matched_policies = result
+        .matched_statements()
+        .iter()
+        .map(|statement| match statement.source_policy_type() {
+            Some(PolicySourceType::Resource) => RESOURCE_POLICY_ID.to_string(),
+            _ => statement.source_policy_id().unwrap_or_default().to_string(),
+        })
+        .collect();
+
+    SimulatedDecision {
+        action: result.eval_action_name().to_string(),
+        decision,
+        matched_policies,
+        missing_context_keys: result.missing_context_values().to_vec(),
+        denied_by_organizations: result
+            .organizations_decision_detail()
+            .is_some_and(|d| !d.allowed_by_organizations()),
+        denied_by_permissions_boundary: result
+            .permissions_boundary_decision_detail()
+            .is_some_and(|d| !d.allowed_by_permissions_boundary()),
+    }
+}
+
+// The simulator needs a type for each key. It is guessed from the values,
+// since `--context` only takes strings.
+fn context_entries(context: &RequestContext) -> Vec<ContextEntry> {
+    context
+        .entries()
+        .map(|(key, values)| {
+            let all = |check: fn(&str) -> bool| values.iter().all(|v| check(v));
+            let (single, many) = if all(|v| v == "true" || v == "false") {
+                (ContextKeyTypeEnum::Boolean, ContextKeyTypeEnum::BooleanList)
+            } else if all(|v| v.parse::<f64>().is_ok()) {
+                (ContextKeyTypeEnum::Numeric, ContextKeyTypeEnum::NumericList)
+            } else if all(|v| v.split('/').next().is_some_and(|ip| ip.parse::<IpAddr>().is_ok())) {
+                (ContextKeyTypeEnum::Ip, ContextKeyTypeEnum::IpList)
+            } else {
+                (ContextKeyTypeEnum::String, ContextKeyTypeEnum::StringList)
+            };
+            let key_type = if values.len() > 1 { many } else { single };
+            ContextEntry::builder()
+                .context_key_name(key)
+                .set_context_key_values(Some(values.to_vec()))
+                .context_key_type(key_type)
+                .build()
+        })
+        .collect()
+}
+
+/// How the simulator's result compares with which-allowed's evaluation.
+#[derive(Debug, Clone, Serialize)]
+pub struct Verification {
+    pub simulator: SimulatedDecision,
+    pub agrees: bool,
+    /// Each way the two results differ, empty when they agree.
+    pub disagreements: Vec<String>,
+}
+
+/// Runs `action_names` through `simulator` as the entity `principal_arn` and
+/// compares each result with the evaluation at the same index. An action the
+/// simulator returned no result for is `None`.
+pub async fn verify_evaluations<V: PolicySimulator>(
+    simulator: &V,
+    principal_arn: &str,
+    policies: &EntityPolicies,
+    action_names: &[String],
+    request: &Request,
+    resource_policy: Option<&ResourcePolicy>,
+    evaluations: &[LayeredEvaluation],
+) -> Result<Vec<Option<Verification>>, Error> {
+    let simulated = simulator
+        .simulate(principal_arn, action_names, request, resource_policy)
+        .await?;
+    let verifications = action_names
+        .iter()
+        .zip(evaluations)
+        .map(|(action_name, evaluation)| {
+            let simulated = simulated.iter().find(|s| s.action.eq_ignore_ascii_case(action_name))?;
+            Some(verify_evaluation(evaluation, policies, simulated.clone()))
+        })
+        .collect();
+    Ok(verifications)
+}
+
+// A conditional decision is not compared, since the simulator treats the
+// missing keys as absent instead. Statements are compared for identity and
+// resource policies only: the simulator reports boundaries and SCPs in the
+// decision but not in its matched statements.
+pub fn verify_evaluation(
+    evaluation: &LayeredEvaluation,
+    policies: &EntityPolicies,
+    simulated: SimulatedDecision,
+) -> Verification {
+    let mut disagreements = vec![];
+
+    if evaluation.decision != Decision::Conditional && evaluation.decision != simulated.decision {
+        disagreements.push(format!(
+            "the simulator decided {}, which-allowed {}",
+            simulated.decision, evaluation.decision
+        ));
+        if simulated.denied_by_organizations {
+            disagreements.push("the simulator applied the account's SCPs, which deny the action".to_string());
+        }
+        if simulated.denied_by_permissions_boundary {
+            disagreements.push("the simulator found the permissions boundary does not allow the action".to_string());
+        }
+    } else if evaluation.decision == simulated.decision {
+        let local = matched_policies(evaluation, policies);
+        for id in &simulated.matched_policies {
+            if !local.iter().any(|(_, local_id)| local_id == id) {
+                disagreements.push(format!("only the simulator matched a statement in {}", id));
+            }
+        }
+        for (name, id) in &local {
+            if !simulated.matched_policies.contains(id) {
+                disagreements.push(format!("only which-allowed matched a statement in {}", name));
+            }
+        }
+    }
+
+    Verification {
+        agrees: disagreements.is_empty(),
+        simulator: simulated,
+        disagreements,
+    }
+}
+
+// The name and simulator ID of each identity and resource policy with
+// statements of the kind that decided the verdict, without duplicates.
+fn matched_policies(evaluation: &LayeredEvaluation, policies: &EntityPolicies) -> Vec<(String, String)> {
+    let mut matched_policies: Vec<(String, String)> = vec![];
+    for layer in &evaluation.layers {
+        let statements: &[MatchedStatement] = match evaluation.decision {
+            Decision::Allowed => &layer.evaluation.allowed,
+            Decision::ExplicitDeny => &layer.evaluation.denied,
+            _ => &[],
+        };
+        for matched in statements {
+            let policy = match layer.layer {
+                PolicyLayer::Identity => {
+                    let holder = policies
+                        .identity_origins
+                        .get(matched.policy_index)
+                        .and_then(|origin| origin.holder.as_ref());
+                    (
+                        matched.policy_name.clone(),
+                        simulator_policy_id(&matched.policy_name, holder),
+                    )
+                }
+                PolicyLayer::ResourcePolicy => (RESOURCE_POLICY_ID.to_string(), RESOURCE_POLICY_ID.to_string()),
+                _ => continue,
+            };
+            if !matched_policies.contains(&policy) {
+                matched_policies.push(policy);
+            }
+        }
+    }
+    matched_policies
+}
+
+// Managed policies are identified by the simulator by name, and inline
+// policies as `<holder type>_<holder name>_<policy name>`.
+fn simulator_policy_id(policy_name: &str, holder: Option<&Entity>) -> String {
+    match holder {
+        Some(holder) => format!("{}_{}_{}", holder.kind, holder.name, policy_name),
+        None => policy_name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluation::evaluate_entity;
+    use crate::source::{EntityKind, PolicyOrigin, PolicyType};
+    use crate::test_util::{policy, request};
+
+    const ALLOW_GET_OBJECT: &str = r#"[{"Effect":"Allow","Action":"s3:GetObject","Resource":"*"}]"#;
+
+    // Returns the same decisions whatever it is asked.
+    struct StubSimulator(Vec<SimulatedDecision>);
+
+    impl PolicySimulator for StubSimulator {
+        async fn simulate(
+            &self,
+            _principal_arn: &str,
+            _action_names: &[String],
+            _request: &Request,
+            _resource_policy: Option<&ResourcePolicy>,
+        ) -> Result<Vec<SimulatedDecision>, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn simulated(decision: Decision, matched_policies: &[&str]) -> SimulatedDecision {
+        SimulatedDecision {
+            action: "s3:GetObject".to_string(),
+            decision,
+            matched_policies: matched_policies.iter().map(|p| p.to_string()).collect(),
+            missing_context_keys: vec![],
+            denied_by_organizations: false,
+            denied_by_permissions_boundary: false,
+        }
+    }
+
+    fn managed(name: &str, statements: &str) -> EntityPolicies {
+        let origin = PolicyOrigin {
+            policy_type: PolicyType::Managed,
+            arn: Some(format!("arn:aws:iam::123456789012:policy/{}", name)),
+            holder: None,
+        };
+        EntityPolicies {
+            identity: vec![(name.to_string(), policy(statements))],
+            identity_origins: vec![origin],
+            ..Default::default()
+        }
+    }
+
+    fn inline(holder: Entity, name: &str, statements: &str) -> EntityPolicies {
+        let origin = PolicyOrigin {
+            policy_type: PolicyType::Inline,
+            arn: None,
+            holder: Some(holder),
+        };
+        EntityPolicies {
+            identity: vec![(name.to_string(), policy(statements))],
+            identity_origins: vec![origin],
+            ..Default::default()
+        }
+    }
+
+    async fn verify(policies: &EntityPolicies, simulator: StubSimulator) -> Verification {
+        let action_names = ["s3:GetObject".to_string()];
+        let request = request("s3:GetObject", None);
+        let evaluations = [evaluate_entity(policies, &[], None, &request)];
+        let arn = "arn:aws:iam::123456789012:user/alice";
+        let verifications = verify_evaluations(&simulator, arn, policies, &action_names, &request, None, &evaluations)
+            .await
+            .expect("the stub always answers");
+        verifications
+            .into_iter()
+            .next()
+            .flatten()
+            .expect("the stub answers for the action")
+    }
+
+    #[tokio::test]
+    async fn agrees_on_the_same_decision_and_policies() {
+        let policies = managed("ReadObjects", ALLOW_GET_OBJECT);
+        let simulator = StubSimulator(vec![simulated(Decision::Allowed, &["ReadObjects"])]);
+
+        let verification = verify(&policies, simulator).await;
+        assert!(verification.agrees, "{:?}", verification.disagreements);
+    }
+
+    #[tokio::test]
+    async fn disagrees_on_a_different_decision() {
+        let policies = managed("ReadObjects", ALLOW_GET_OBJECT);
+        let mut denied = simulated(Decision::ImplicitDeny, &[]);
+        denied.denied_by_organizations = true;
+
+        let verification = verify(&policies, StubSimulator(vec![denied])).await;
+        assert!(!verification.agrees);
+        assert_eq!(
+            verification.disagreements,
+            [
+                "the simulator decided ImplicitDeny, which-allowed Allowed",
+                "the simulator applied the account's SCPs, which deny the action",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn disagrees_on_the_policies_behind_the_same_decision() {
+        let policies = managed("ReadObjects", ALLOW_GET_OBJECT);
+        let simulator = StubSimulator(vec![simulated(Decision::Allowed, &["ReadEverything"])]);
+
+        let verification = verify(&policies, simulator).await;
+        assert_eq!(
+            verification.disagreements,
+            [
+                "only the simulator matched a statement in ReadEverything",
+                "only which-allowed matched a statement in ReadObjects",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn inline_policies_match_only_their_own_holder() {
+        let alice = Entity::new(EntityKind::User, "alice");
+        let policies = inline(alice, "bar", ALLOW_GET_OBJECT);
+
+        let verification = verify(
+            &policies,
+            StubSimulator(vec![simulated(Decision::Allowed, &["user_alice_bar"])]),
+        )
+        .await;
+        assert!(verification.agrees, "{:?}", verification.disagreements);
+
+        for id in ["foo_bar", "user_bob_bar", "group_alice_bar"] {
+            let verification = verify(&policies, StubSimulator(vec![simulated(Decision::Allowed, &[id])])).await;
+            assert!(
+                !verification.agrees,
+                "{} should not match alice's inline policy bar",
+                id
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn actions_the_simulator_skipped_are_unverified() {
+        let policies = managed("ReadObjects", ALLOW_GET_OBJECT);
+        let mut other = simulated(Decision::Allowed, &["ReadObjects"]);
+        other.action = "s3:PutObject".to_string();
+
+        let request = request("s3:GetObject", None);
+        let evaluations = [evaluate_entity(&policies, &[], None, &request)];
+        let verifications = verify_evaluations(
+            &StubSimulator(vec![other]),
+            "arn:aws:iam::123456789012:user/alice",
+            &policies,
+            &["s3:GetObject".to_string()],
+            &request,
+            None,
+            &evaluations,
+        )
+        .await
+        .expect("the stub always answers");
+        assert!(verifications[0].is_none());
+    }
+
+    #[test]
+    fn context_key_types_are_guessed_from_the_values() {
+        let pairs = [
+            "aws:SecureTransport=true",
+            "s3:max-keys=10",
+            "aws:SourceIp=203.0.113.0/24",
+            "aws:SourceIp=2001:db8::1",
+            "aws:PrincipalTag/team=dev",
+            "aws:TagKeys=10",
+            "aws:TagKeys=team",
+        ]
+        .map(String::from);
+        let context = RequestContext::from_pairs(&pairs).unwrap();
+
+        let entries = context_entries(&context);
+        let key_type = |key: &str| {
+            let entry = entries
+                .iter()
+                .find(|e| e.context_key_name() == Some(key))
+                .expect("key is sent");
+            entry.context_key_type().cloned()
+        };
+        assert_eq!(key_type("aws:securetransport"), Some(ContextKeyTypeEnum::Boolean));
+        assert_eq!(key_type("s3:max-keys"), Some(ContextKeyTypeEnum::Numeric));
+        assert_eq!(key_type("aws:sourceip"), Some(ContextKeyTypeEnum::IpList));
+        assert_eq!(key_type("aws:principaltag/team"), Some(ContextKeyTypeEnum::String));
+        assert_eq!(key_type("aws:tagkeys"), Some(ContextKeyTypeEnum::StringList));
+    }
+}